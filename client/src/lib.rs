@@ -0,0 +1,574 @@
+//! An async client for the minigolf server, built on the `protocol` crate.
+//!
+//! [`Client::connect`] does the whole login the server expects, `c new`, `version`,
+//! `s tlog`, `language`, `logintype` and `ttlogin`, and returns once the server
+//! has put the client into the lobby selection. After that [`Client::recv`] reads
+//! packets, answering pings on the way, and the other methods send the packets of
+//! the real client with the next packet number.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use protocol::{
+    client::{
+        ClientToServer, GameBack, GameBeginStroke, GameEndStroke, GameSay, Language, LobbyCmpt,
+        LobbyCspt, LobbyJmpt, LobbySay, LobbySayP, LobbySelectSelect, LoginType, New, Pong,
+        TTLogin, TLog, Version,
+    },
+    codec::{Decoded, MinigolfCodec},
+    common::{
+        Collision, DLobbyType, DLoginType, NoneAsTab, NonEmptyOption, PacketNumber, Parse,
+        PlayerInfo, Scoring, TrackType, WaterEvent, WeightEnd,
+    },
+    crypt::{Ciphers, ConnCipher, GameCipher, CIPHER_MAGIC_DEFAULT},
+    server::ServerToClient,
+    stroke::Stroke,
+    version::ProtocolVersion,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs},
+    time::{timeout, timeout_at, Instant},
+};
+
+/// How long [`Client::connect`] waits for each answer of the server.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest line the client accepts. The server's lobby user list grows with
+/// every user, so this is far beyond what the server accepts from clients.
+pub const MAX_LINE_LENGTH: usize = 1 << 20;
+
+/// How to log in.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// `None` logs in as a guest, the server picks a `~anonym` name.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub language: String,
+    pub version: ProtocolVersion,
+    pub login_type: DLoginType,
+    /// Whether the server encrypts this connection, it does when it runs with
+    /// `CIPHERS=full` and the host isn't in its `PLAINTEXT_HOSTS`.
+    pub ciphers: bool,
+    /// Sent as `s tlog` during the login so the server's diagnostics show who
+    /// connected.
+    pub log: Option<String>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            username: None,
+            password: None,
+            language: "en".to_string(),
+            version: ProtocolVersion::LATEST,
+            login_type: DLoginType::Nr,
+            ciphers: false,
+            log: Some(format!("rust client {}", env!("CARGO_PKG_VERSION"))),
+        }
+    }
+}
+
+/// Settings of a multiplayer game, `lobby\tcmpt`.
+#[derive(Debug, Clone)]
+pub struct GameSettings {
+    pub name: Option<String>,
+    pub password: Option<String>,
+    pub max_players: usize,
+    pub num_tracks: usize,
+    pub track_type: TrackType,
+    pub max_strokes: i32,
+    /// Seconds per stroke, 0 is unlimited.
+    pub time_limit: i32,
+    pub water_event: WaterEvent,
+    pub collision: Collision,
+    pub scoring: Scoring,
+    pub weighted_end: WeightEnd,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            name: None,
+            password: None,
+            max_players: 2,
+            num_tracks: 1,
+            track_type: TrackType::All,
+            max_strokes: 20,
+            time_limit: 60,
+            water_event: WaterEvent::BackToStart,
+            collision: Collision::Yes,
+            scoring: Scoring::Score,
+            weighted_end: WeightEnd::None,
+        }
+    }
+}
+
+/// A logged in connection to the server.
+pub struct Client {
+    stream: TcpStream,
+    codec: MinigolfCodec,
+    ciphers: Ciphers,
+    buffer: [u8; 4096],
+    sent: u32,
+    network_id: usize,
+    logs: i32,
+}
+
+impl Client {
+    /// Connects to `addr` and logs in.
+    pub async fn connect(addr: impl ToSocketAddrs, options: ClientOptions) -> Result<Client> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .context("failed to connect")?;
+        stream.set_nodelay(true)?;
+        let mut client = Client {
+            stream,
+            codec: MinigolfCodec::with_max_line_length(MAX_LINE_LENGTH),
+            ciphers: Ciphers::none(),
+            buffer: [0; 4096],
+            sent: 0,
+            network_id: 0,
+            logs: 0,
+        };
+        timeout(HANDSHAKE_TIMEOUT * 3, client.login(&options))
+            .await
+            .context("login timed out")??;
+        Ok(client)
+    }
+
+    async fn login(&mut self, options: &ClientOptions) -> Result<()> {
+        let seed = loop {
+            if let ServerToClient::Io(io) = self.recv_handshake().await? {
+                break io.seed;
+            }
+        };
+        loop {
+            if let ServerToClient::Ctr(_) = self.recv_handshake().await? {
+                break;
+            }
+        }
+        if options.ciphers {
+            self.set_ciphers(Ciphers::new(
+                Some(GameCipher::new()),
+                Some(ConnCipher::new(CIPHER_MAGIC_DEFAULT, seed)),
+            ));
+        }
+
+        self.send_raw(&New {}).await?;
+        self.network_id = match self.recv_handshake().await? {
+            ServerToClient::Id(id) => id.value,
+            packet => bail!("expected c id, got {:?}", packet),
+        };
+
+        let packet_number = self.next_num();
+        self.send_raw(&Version {
+            packet_number,
+            version: options.version.number(),
+        })
+        .await?;
+        // The server reads everything after the version with its command table
+        if options.ciphers {
+            let mut ciphers = self.ciphers.clone();
+            ciphers.set_game_cipher(Some(options.version.game_cipher()));
+            self.set_ciphers(ciphers);
+        }
+        if let Some(log) = &options.log {
+            self.log("login", log).await?;
+        }
+        match self.recv_handshake().await? {
+            ServerToClient::VersOk(_) => {}
+            packet => bail!("server refused version {}: {:?}", options.version, packet),
+        }
+
+        let packet_number = self.next_num();
+        self.send_raw(&Language {
+            packet_number,
+            languge: options.language.clone(),
+        })
+        .await?;
+        let packet_number = self.next_num();
+        self.send_raw(&LoginType {
+            packet_number,
+            login_type: options.login_type,
+        })
+        .await?;
+        match self.recv_handshake().await? {
+            ServerToClient::StatusLogin(status) if status.status.0.is_none() => {}
+            packet => bail!("login refused: {:?}", packet),
+        }
+        let packet_number = self.next_num();
+        self.send_raw(&TTLogin {
+            packet_number,
+            username: NoneAsTab(options.username.clone()),
+            password: NoneAsTab(options.password.clone()),
+        })
+        .await?;
+        loop {
+            match self.recv_handshake().await? {
+                ServerToClient::StatusLobbySelect(_) => return Ok(()),
+                ServerToClient::BasicInfo(_) => {}
+                packet => bail!("login failed: {:?}", packet),
+            }
+        }
+    }
+
+    async fn recv_handshake(&mut self) -> Result<ServerToClient> {
+        self.read(Some(Instant::now() + HANDSHAKE_TIMEOUT))
+            .await?
+            .context("server stopped answering")
+    }
+
+    fn set_ciphers(&mut self, ciphers: Ciphers) {
+        self.codec.set_ciphers(ciphers.clone());
+        self.ciphers = ciphers;
+    }
+
+    /// The id the server gave this connection with `c id`.
+    pub fn network_id(&self) -> usize {
+        self.network_id
+    }
+
+    /// The number for the next `d` packet. Packets built by hand for [`send`]
+    /// must take theirs from here.
+    ///
+    /// [`send`]: Client::send
+    pub fn next_num(&mut self) -> PacketNumber {
+        self.sent += 1;
+        PacketNumber(self.sent)
+    }
+
+    async fn send_raw(&mut self, packet: &impl Parse) -> Result<()> {
+        let line = self.ciphers.encrypt(&packet.as_string());
+        self.stream.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    pub async fn send(&mut self, packet: ClientToServer) -> Result<()> {
+        self.send_raw(&packet).await
+    }
+
+    /// The next packet, `None` once `deadline` passed without one.
+    async fn read(&mut self, deadline: Option<Instant>) -> Result<Option<ServerToClient>> {
+        loop {
+            match self.codec.next_packet()? {
+                Some(Decoded::Packet(packet)) => return Ok(Some(packet)),
+                Some(Decoded::Unknown(line)) => log::warn!("unknown packet {:?}", line),
+                Some(Decoded::Malformed { line, reason }) => {
+                    log::warn!("malformed packet {:?}: {}", line, reason)
+                }
+                None => {
+                    let read = match deadline {
+                        Some(deadline) => {
+                            match timeout_at(deadline, self.stream.read(&mut self.buffer)).await {
+                                Ok(read) => read?,
+                                Err(_) => return Ok(None),
+                            }
+                        }
+                        None => self.stream.read(&mut self.buffer).await?,
+                    };
+                    if read == 0 {
+                        bail!("server closed the connection");
+                    }
+                    self.codec.accept(&self.buffer[..read]);
+                }
+            }
+        }
+    }
+
+    /// The next packet from the server. Pings are answered and not returned.
+    pub async fn recv(&mut self) -> Result<ServerToClient> {
+        loop {
+            match self.read(None).await?.expect("no deadline") {
+                ServerToClient::Ping(_) => self.send_raw(&Pong {}).await?,
+                packet => return Ok(packet),
+            }
+        }
+    }
+
+    /// Like [`recv`](Client::recv) but gives up at `deadline` and returns `None`.
+    ///
+    /// Unlike wrapping `recv` in a timeout this never abandons a pong halfway
+    /// written, so it is the way to wait for packets and a timer at once.
+    pub async fn recv_before(&mut self, deadline: Instant) -> Result<Option<ServerToClient>> {
+        loop {
+            match self.read(Some(deadline)).await? {
+                Some(ServerToClient::Ping(_)) => self.send_raw(&Pong {}).await?,
+                packet => return Ok(packet),
+            }
+        }
+    }
+
+    /// Reads packets until `filter` picks one, the rest are dropped.
+    pub async fn recv_until<T>(
+        &mut self,
+        mut filter: impl FnMut(ServerToClient) -> Option<T>,
+    ) -> Result<T> {
+        loop {
+            if let Some(result) = filter(self.recv().await?) {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Sends `s tlog`, the server keeps it with its diagnostics.
+    pub async fn log(&mut self, id: &str, message: &str) -> Result<()> {
+        self.logs += 1;
+        let log = TLog {
+            count: self.logs,
+            id: id.to_string(),
+            log: message.lines().map(str::to_string).collect(),
+        };
+        self.send_raw(&log).await
+    }
+
+    pub async fn select_lobby(&mut self, lobby_type: DLobbyType) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::LobbySelectSelect(LobbySelectSelect {
+            packet_number,
+            lobby_type,
+        }))
+        .await
+    }
+
+    /// Says `message` to everyone in the lobby.
+    pub async fn say(&mut self, message: &str) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::LobbySay(LobbySay {
+            packet_number,
+            lobby_tab: "l".to_string(),
+            message: message.to_string(),
+        }))
+        .await
+    }
+
+    /// Says `message` to `destination` only, who must be in a lobby.
+    pub async fn say_private(&mut self, destination: &str, message: &str) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::LobbySayP(LobbySayP {
+            packet_number,
+            destination: destination.to_string(),
+            message: message.to_string(),
+        }))
+        .await
+    }
+
+    /// Says `message` to the other players of the game.
+    pub async fn say_game(&mut self, message: &str) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::GameSay(GameSay {
+            packet_number,
+            message: message.to_string(),
+        }))
+        .await
+    }
+
+    /// Starts a single player game, the client must be in the single player lobby.
+    pub async fn create_solo_game(
+        &mut self,
+        num_tracks: usize,
+        track_type: TrackType,
+        water_event: WaterEvent,
+    ) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::LobbyCspt(LobbyCspt {
+            packet_number,
+            num_tracks,
+            track_type,
+            water_event,
+        }))
+        .await
+    }
+
+    /// Opens a multiplayer game, the client must be in the multiplayer lobby.
+    pub async fn create_game(&mut self, settings: GameSettings) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::LobbyCmpt(LobbyCmpt {
+            packet_number,
+            game_name: NonEmptyOption(settings.name),
+            password: NonEmptyOption(settings.password),
+            permission: 0,
+            max_players: settings.max_players,
+            num_tracks: settings.num_tracks,
+            track_types: settings.track_type,
+            max_strokes: settings.max_strokes,
+            time_limit: settings.time_limit,
+            water_event: settings.water_event,
+            collision: settings.collision,
+            track_scoring: settings.scoring,
+            track_scoring_weighted_end: settings.weighted_end,
+        }))
+        .await
+    }
+
+    /// Joins multiplayer game `network_id`.
+    pub async fn join_game(&mut self, network_id: usize) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::LobbyJmpt(LobbyJmpt {
+            packet_number,
+            network_id,
+        }))
+        .await
+    }
+
+    /// Shoots, it must be this client's turn.
+    pub async fn stroke(&mut self, stroke: Stroke) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::GameBeginStroke(GameBeginStroke {
+            packet_number,
+            coords: stroke,
+        }))
+        .await
+    }
+
+    /// Reports where the stroke of player `index` ended. Every player sends this
+    /// for every stroke.
+    pub async fn end_stroke(&mut self, index: usize, in_hole: PlayerInfo) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::GameEndStroke(GameEndStroke {
+            packet_number,
+            index,
+            in_hole,
+        }))
+        .await
+    }
+
+    /// Leaves the game for the lobby.
+    pub async fn leave_game(&mut self) -> Result<()> {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::GameBack(GameBack { packet_number }))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::{
+        client::ClientToServer,
+        codec::{Decoded, MinigolfCodec},
+        common::{DLobbyType, Parse},
+        crypt::{Ciphers, ConnCipher, GameCipher, CIPHER_MAGIC_DEFAULT},
+        server::ServerToClient,
+        version::ProtocolVersion,
+    };
+    use std::time::Duration;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        time::Instant,
+    };
+
+    use super::{Client, ClientOptions};
+
+    /// The server side of one connection, as `initial_handler` does it.
+    struct FakeServer {
+        stream: TcpStream,
+        codec: MinigolfCodec,
+        ciphers: Ciphers,
+    }
+
+    impl FakeServer {
+        async fn read(&mut self) -> String {
+            let mut buffer = [0; 1024];
+            loop {
+                match self.codec.next_packet::<ClientToServer>().unwrap() {
+                    Some(Decoded::Packet(packet)) => return packet.as_string(),
+                    Some(Decoded::Unknown(line)) => return line,
+                    Some(Decoded::Malformed { line, .. }) => panic!("malformed {:?}", line),
+                    None => {}
+                }
+                let read = self.stream.read(&mut buffer).await.unwrap();
+                assert!(read > 0, "client hung up");
+                self.codec.accept(&buffer[..read]);
+            }
+        }
+
+        async fn write(&mut self, lines: &str) {
+            let lines = self.ciphers.encrypt(lines);
+            self.stream.write_all(lines.as_bytes()).await.unwrap();
+        }
+    }
+
+    async fn serve(listener: TcpListener, ciphers: bool) -> Vec<String> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut server = FakeServer {
+            stream,
+            codec: MinigolfCodec::new(),
+            ciphers: Ciphers::none(),
+        };
+        server.write("h 1\nc io 148153586\nc crt 250\nc ctr\n").await;
+        if ciphers {
+            server.ciphers = Ciphers::new(
+                Some(GameCipher::new()),
+                Some(ConnCipher::new(CIPHER_MAGIC_DEFAULT, 148153586)),
+            );
+            server.codec.set_ciphers(server.ciphers.clone());
+        }
+        let mut received = vec![server.read().await];
+        server.write("c id 7\n").await;
+        received.push(server.read().await);
+        if ciphers {
+            let game_cipher = ProtocolVersion::V35.game_cipher();
+            server.ciphers.set_game_cipher(Some(game_cipher));
+            server.codec.set_ciphers(server.ciphers.clone());
+        }
+        received.push(server.read().await);
+        server.write("d 0 versok\n").await;
+        received.push(server.read().await);
+        received.push(server.read().await);
+        server.write("d 1 status\tlogin\n").await;
+        received.push(server.read().await);
+        server
+            .write("d 2 basicinfo\tt\t0\tt\tf\nd 3 status\tlobbyselect\t300\nc ping\n")
+            .await;
+        received.push(server.read().await);
+        server.write("d 4 lobbyselect\tnop\t0\t0\t1\n").await;
+        received.push(server.read().await);
+        received
+    }
+
+    async fn login(ciphers: bool) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, ciphers));
+
+        let options = ClientOptions {
+            ciphers,
+            log: Some("test".to_string()),
+            ..Default::default()
+        };
+        let mut client = Client::connect(addr, options).await.unwrap();
+        assert_eq!(client.network_id(), 7);
+        let packet = client.recv().await.unwrap();
+        assert!(matches!(packet, ServerToClient::LobbySelectNop(_)), "{:?}", packet);
+        let deadline = Instant::now() + Duration::from_millis(50);
+        assert!(client.recv_before(deadline).await.unwrap().is_none());
+        client.select_lobby(DLobbyType::Multi).await.unwrap();
+
+        let received = server.await.unwrap();
+        assert_eq!(
+            received,
+            [
+                "c new\n",
+                "d 1 version\t35\n",
+                "s tlog\t1\tlogin\ttest\n",
+                "d 2 language\ten\n",
+                "d 3 logintype\tnr\n",
+                "d 4 ttlogin\t\t\n",
+                "c pong\n",
+                "d 5 lobbyselect\tselect\tx\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn login_plaintext() {
+        login(false).await;
+    }
+
+    #[tokio::test]
+    async fn login_encrypted() {
+        login(true).await;
+    }
+}
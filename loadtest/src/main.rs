@@ -0,0 +1,241 @@
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use clap::Parser;
+use client::{Client, ClientOptions};
+use player::{Player, Scenario, Settings};
+use server::{MemoryUsage, ServerProcess};
+use stats::Stats;
+use tokio::time::{sleep, sleep_until, timeout, Instant};
+
+mod player;
+mod server;
+mod stats;
+
+/// Runs simulated clients against a server and reports how it held up
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Server to connect to
+    #[arg(short, long, default_value = "127.0.0.1:4242")]
+    addr: SocketAddr,
+
+    /// Number of simulated clients
+    #[arg(short = 'n', long, default_value_t = 100)]
+    clients: usize,
+
+    /// What the clients do once logged in
+    #[arg(short, long, value_enum, default_value_t = Scenario::Chat)]
+    scenario: Scenario,
+
+    /// Seconds the test runs, including the ramp up
+    #[arg(short, long, default_value_t = 60)]
+    duration: u64,
+
+    /// Clients logging in per second during the ramp up
+    #[arg(short, long, default_value_t = 50.0)]
+    rate: f64,
+
+    /// Milliseconds between the chat messages of a client
+    #[arg(long, default_value_t = 5000)]
+    chat_interval: u64,
+
+    /// Players per game in the games and play scenarios
+    #[arg(long, default_value_t = 2)]
+    players: usize,
+
+    /// Tracks per game in the play scenario
+    #[arg(long, default_value_t = 1)]
+    tracks: usize,
+
+    /// Seconds clients stay in a game in the games scenario
+    #[arg(long, default_value_t = 20)]
+    game_time: u64,
+
+    /// Encrypt the connections, the server must run with CIPHERS=full
+    #[arg(short, long)]
+    ciphers: bool,
+
+    /// Start this server binary for the test, which also watches its ticks and memory
+    #[arg(long)]
+    server: Option<PathBuf>,
+
+    /// Watch the memory of an already running server with this process id
+    #[arg(long, conflicts_with = "server")]
+    pid: Option<u32>,
+}
+
+/// How often the memory of the server is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// How often progress is printed.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+fn mib(kib: u64) -> String {
+    format!("{:.1} MiB", kib as f64 / 1024.0)
+}
+
+async fn run_client(
+    index: usize,
+    args: Arc<Args>,
+    settings: Arc<Settings>,
+    stats: Arc<Mutex<Stats>>,
+) {
+    let options = ClientOptions {
+        username: Some(format!("~load-{}", index)),
+        ciphers: args.ciphers,
+        log: Some(format!("loadtest client {}", index)),
+        ..Default::default()
+    };
+    let client = match Client::connect(args.addr, options).await {
+        Ok(client) => client,
+        Err(e) => {
+            stats.lock().unwrap().fail(format!("{:#}", e));
+            return;
+        }
+    };
+    stats.lock().unwrap().connected += 1;
+    let player = Player::new(client, index, settings, stats.clone());
+    if let Err(e) = player.run().await {
+        stats.lock().unwrap().disconnect(format!("{:#}", e));
+    }
+}
+
+fn print_report(
+    args: &Args,
+    stats: &Stats,
+    server: Option<&ServerProcess>,
+    memory: Option<MemoryUsage>,
+) {
+    println!();
+    println!(
+        "{:?} scenario, {} clients for {}s against {}",
+        args.scenario, args.clients, args.duration, args.addr
+    );
+    println!("logged in  {} of {}", stats.connected, args.clients);
+    for (label, reasons) in [("failed", &stats.failed), ("dropped", &stats.dropped)] {
+        println!("{:<10} {}", label, reasons.values().sum::<usize>());
+        for (reason, count) in stats::reasons(reasons) {
+            println!("           {}x {}", count, reason);
+        }
+    }
+    println!("latency    {}", stats.latency);
+    println!("echo       {}", stats.echo);
+    println!("chat       {} messages sent", stats.sent);
+    println!(
+        "games      {} entered, {} played to the end, {} strokes",
+        stats.games, stats.rounds, stats.strokes
+    );
+    match server.map(ServerProcess::overruns) {
+        Some(Some(overruns)) => println!(
+            "ticks      {} overruns, worst {}ms",
+            overruns.count,
+            overruns.worst.as_millis()
+        ),
+        Some(None) => println!("ticks      the server wrote no TICK_STATS"),
+        None => println!("ticks      not watched, start the server with --server"),
+    }
+    match memory {
+        Some(memory) => println!(
+            "memory     {} at the start, {} peak, {} at the end",
+            mib(memory.first),
+            mib(memory.peak),
+            mib(memory.last)
+        ),
+        None => println!("memory     not watched, pass --server or --pid"),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let args = Arc::new(Args::parse());
+
+    let mut server = match &args.server {
+        Some(path) => {
+            let mut server = ServerProcess::spawn(path, args.ciphers)?;
+            server.wait_ready(args.addr).await?;
+            Some(server)
+        }
+        None => None,
+    };
+    let pid = args
+        .pid
+        .or_else(|| server.as_ref().and_then(ServerProcess::pid));
+
+    let origin = Instant::now();
+    let deadline = origin + Duration::from_secs(args.duration);
+    let settings = Arc::new(Settings {
+        scenario: args.scenario,
+        origin,
+        deadline,
+        chat_interval: Duration::from_millis(args.chat_interval),
+        players: args.players,
+        tracks: args.tracks,
+        game_time: Duration::from_secs(args.game_time),
+    });
+    let stats = Arc::new(Mutex::new(Stats::default()));
+
+    let mut clients = Vec::with_capacity(args.clients);
+    for index in 0..args.clients {
+        let start = origin + Duration::from_secs_f64(index as f64 / args.rate);
+        let (args, settings, stats) = (args.clone(), settings.clone(), stats.clone());
+        clients.push(tokio::spawn(async move {
+            sleep_until(start).await;
+            if Instant::now() < settings.deadline {
+                run_client(index, args, settings, stats).await;
+            }
+        }));
+    }
+
+    let mut memory = pid.map(|_| MemoryUsage::default());
+    let mut next_progress = origin + PROGRESS_INTERVAL;
+    while Instant::now() < deadline {
+        if let (Some(pid), Some(memory)) = (pid, &mut memory) {
+            if let Some(kib) = server::memory(pid) {
+                memory.sample(kib);
+            }
+        }
+        if Instant::now() >= next_progress {
+            next_progress += PROGRESS_INTERVAL;
+            let stats = stats.lock().unwrap();
+            let mut line = format!(
+                "[{:>3}s] online {}/{}, dropped {}, latency p99 ",
+                origin.elapsed().as_secs(),
+                stats.online(),
+                args.clients,
+                stats.dropped.values().sum::<usize>()
+            );
+            match stats.latency.percentile(99.0) {
+                Some(p99) => line += &format!("{:.1}ms", p99.as_secs_f64() * 1000.0),
+                None => line += "-",
+            }
+            if let Some(overruns) = server.as_ref().and_then(ServerProcess::overruns) {
+                line += &format!(", overruns {}", overruns.count);
+            }
+            if let Some(memory) = memory {
+                line += &format!(", memory {}", mib(memory.last));
+            }
+            println!("{}", line);
+        }
+        if let Some(server) = &mut server {
+            if !server.is_running() {
+                println!("the server exited");
+                break;
+            }
+        }
+        sleep(SAMPLE_INTERVAL.min(deadline.saturating_duration_since(Instant::now()))).await;
+    }
+
+    // Clients stop at the deadline, only ones stuck logging in are left behind
+    for client in clients {
+        let _ = timeout(Duration::from_secs(5), client).await;
+    }
+    let stats = stats.lock().unwrap();
+    print_report(&args, &stats, server.as_ref(), memory);
+    Ok(())
+}
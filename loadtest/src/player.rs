@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use client::{Client, GameSettings};
+use protocol::{
+    common::DLobbyType,
+    mirror::{aim, nearest_hole, random_stroke, GameMirror},
+    server::{Game, ServerToClient},
+    stroke::Stroke,
+};
+use rand::{seq::IteratorRandom, Rng};
+use tokio::time::Instant;
+
+use crate::stats::Stats;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Scenario {
+    /// Sit in the multiplayer lobby, answer pings and only message themselves
+    Idle,
+    /// Chat in the multiplayer lobby
+    Chat,
+    /// Create and join multiplayer games, chat in them and leave again
+    Games,
+    /// Create and join multiplayer games and play them to the end
+    Play,
+}
+
+/// How the simulated players behave, the same for all of them.
+#[derive(Debug)]
+pub struct Settings {
+    pub scenario: Scenario,
+    /// Everything is measured from here.
+    pub origin: Instant,
+    /// When the players stop.
+    pub deadline: Instant,
+    pub chat_interval: Duration,
+    /// Players per game.
+    pub players: usize,
+    pub tracks: usize,
+    /// How long players stay in a game in the games scenario.
+    pub game_time: Duration,
+}
+
+/// How often a player looks whether it has something to do.
+const ACT_INTERVAL: Duration = Duration::from_millis(200);
+/// How long to wait for the server to answer a create or join before trying again.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(3);
+/// Chat messages that measure latency, followed by the microseconds since
+/// [`Settings::origin`] they were sent at. Players in a lobby also send these
+/// privately to themselves, whatever the scenario.
+const LATENCY_PREFIX: &str = "lt ";
+
+/// Where a player is.
+enum Place {
+    LobbySelect,
+    Lobby,
+    Game(Box<PlayerGame>),
+    /// Left a game, packets of it may still arrive until the lobby's.
+    Leaving,
+}
+
+/// The game a player is in.
+struct PlayerGame {
+    entered: Instant,
+    mirror: GameMirror,
+}
+
+impl PlayerGame {
+    fn new() -> Self {
+        Self {
+            entered: Instant::now(),
+            mirror: GameMirror::new(),
+        }
+    }
+
+    /// Roughly towards the nearest hole, the load test doesn't need good players.
+    fn choose(&self, index: usize) -> Stroke {
+        let Some(simulation) = self.mirror.simulation() else {
+            return random_stroke();
+        };
+        let ball = simulation.ball(index).position;
+        let Some(hole) = nearest_hole(self.mirror.holes(), ball) else {
+            return random_stroke();
+        };
+        let mut rng = rand::thread_rng();
+        let angle = (hole.y - ball.y).atan2(hole.x - ball.x) + rng.gen_range(-0.3..0.3);
+        let distance =
+            (hole.x - ball.x).hypot(hole.y - ball.y).min(200.0) * rng.gen_range(0.5..1.5);
+        aim(ball, angle, distance)
+    }
+}
+
+/// One simulated client, logged in and playing its scenario until the deadline.
+pub struct Player {
+    client: Client,
+    index: usize,
+    /// Own name, from `lobby\townjoin`.
+    name: Option<String>,
+    settings: Arc<Settings>,
+    stats: Arc<Mutex<Stats>>,
+    place: Place,
+    /// Multiplayer games with free slots, by id.
+    open_games: HashMap<usize, usize>,
+    /// When the last create or join was sent, until the server answers it.
+    joining: Option<Instant>,
+    next_action: Instant,
+    next_chat: Instant,
+}
+
+impl Player {
+    pub fn new(
+        client: Client,
+        index: usize,
+        settings: Arc<Settings>,
+        stats: Arc<Mutex<Stats>>,
+    ) -> Self {
+        // Spread the chat of all players over the interval
+        let offset = settings.chat_interval.mul_f64(rand::thread_rng().gen());
+        Self {
+            client,
+            index,
+            name: None,
+            place: Place::LobbySelect,
+            open_games: HashMap::new(),
+            joining: None,
+            next_action: Instant::now(),
+            next_chat: Instant::now() + offset,
+            settings,
+            stats,
+        }
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        self.client.select_lobby(DLobbyType::Multi).await?;
+        loop {
+            let deadline = self.settings.deadline;
+            if Instant::now() >= deadline {
+                return Ok(());
+            }
+            if let Some(packet) = self
+                .client
+                .recv_before(self.next_action.min(deadline))
+                .await?
+            {
+                self.handle(packet).await?;
+            }
+            if Instant::now() >= self.next_action {
+                self.next_action = Instant::now() + ACT_INTERVAL;
+                self.act().await?;
+            }
+        }
+    }
+
+    fn stats(&self) -> std::sync::MutexGuard<'_, Stats> {
+        self.stats.lock().unwrap()
+    }
+
+    fn latency_message(&self) -> String {
+        let micros = self.settings.origin.elapsed().as_micros();
+        format!("{}{}", LATENCY_PREFIX, micros)
+    }
+
+    /// How long ago a latency message was sent, `None` for other messages.
+    fn latency(&self, message: &str) -> Option<Duration> {
+        let micros = message
+            .strip_prefix(LATENCY_PREFIX)
+            .and_then(|micros| micros.parse().ok())?;
+        let sent = self.settings.origin + Duration::from_micros(micros);
+        Some(Instant::now() - sent)
+    }
+
+    fn record_latency(&self, message: &str) {
+        if let Some(latency) = self.latency(message) {
+            self.stats().latency.record(latency);
+        }
+    }
+
+    fn track_game(&mut self, game: &Game) {
+        let free = game.max_players.saturating_sub(game.num_players);
+        if game.passworded || free == 0 {
+            self.open_games.remove(&game.id);
+        } else {
+            self.open_games.insert(game.id, free);
+        }
+    }
+
+    /// Whether this player opens games for the others to join.
+    fn is_host(&self) -> bool {
+        self.index.is_multiple_of(self.settings.players.max(1))
+    }
+
+    async fn act(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let scenario = self.settings.scenario;
+        let chat = now >= self.next_chat;
+        if chat {
+            self.next_chat = now + self.settings.chat_interval;
+        }
+        match &self.place {
+            Place::LobbySelect | Place::Leaving => {}
+            Place::Lobby => {
+                if chat && scenario == Scenario::Chat {
+                    let message = self.latency_message();
+                    self.client.say(&message).await?;
+                    self.stats().sent += 1;
+                }
+                if let Some(name) = self.name.as_deref().filter(|_| chat) {
+                    let message = self.latency_message();
+                    self.client.say_private(name, &message).await?;
+                }
+                if matches!(scenario, Scenario::Games | Scenario::Play)
+                    && self.joining.is_none_or(|sent| now > sent + JOIN_TIMEOUT)
+                {
+                    self.enter_game().await?;
+                }
+            }
+            Place::Game(game) => {
+                let leave =
+                    scenario == Scenario::Games && now > game.entered + self.settings.game_time;
+                if leave {
+                    self.client.leave_game().await?;
+                    self.place = Place::Leaving;
+                } else if chat {
+                    let message = self.latency_message();
+                    self.client.say_game(&message).await?;
+                    self.stats().sent += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn enter_game(&mut self) -> Result<()> {
+        if self.is_host() {
+            let settings = GameSettings {
+                max_players: self.settings.players,
+                num_tracks: self.settings.tracks,
+                max_strokes: 10,
+                ..Default::default()
+            };
+            self.client.create_game(settings).await?;
+            self.joining = Some(Instant::now());
+            return Ok(());
+        }
+        let game = self
+            .open_games
+            .iter_mut()
+            .filter(|(_, free)| **free > 0)
+            .choose(&mut rand::thread_rng());
+        if let Some((&id, free)) = game {
+            // Count the slot as taken so the next try goes elsewhere if this one fails
+            *free -= 1;
+            self.client.join_game(id).await?;
+            self.joining = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    async fn handle(&mut self, packet: ServerToClient) -> Result<()> {
+        match packet {
+            ServerToClient::StatusLobby(_) => {
+                self.place = Place::Lobby;
+                self.joining = None;
+            }
+            ServerToClient::StatusGame(_) => {
+                self.place = Place::Game(Box::new(PlayerGame::new()));
+                self.joining = None;
+                self.stats().games += 1;
+            }
+            ServerToClient::LobbyGamelistFull(list) => {
+                self.open_games.clear();
+                for game in list.games.iter().flatten() {
+                    self.track_game(game);
+                }
+            }
+            ServerToClient::LobbyGamelistAdd(add) => self.track_game(&add.game),
+            ServerToClient::LobbyGamelistChange(change) => self.track_game(&change.game),
+            ServerToClient::LobbyGamelistRemove(remove) => {
+                self.open_games.remove(&remove.id);
+            }
+            ServerToClient::LobbyOwnJoin(join) => {
+                let name = join.own_info.id_username;
+                // The server prefixes names with `3:`
+                let name = name.split_once(':').map_or(name.as_str(), |(_, name)| name);
+                self.name = Some(name.to_string());
+            }
+            ServerToClient::LobbySay(say) => self.record_latency(&say.message),
+            ServerToClient::LobbySayP(say) if self.name.as_ref() == Some(&say.from) => {
+                if let Some(latency) = self.latency(&say.message) {
+                    self.stats().echo.record(latency);
+                }
+            }
+            ServerToClient::GameSay(say) => self.record_latency(&say.message),
+            ServerToClient::GameStartTurn(turn) => self.play_turn(turn.index).await?,
+            ServerToClient::GameEnd(_) => {
+                if self.settings.scenario == Scenario::Play {
+                    self.stats().rounds += 1;
+                    self.client.leave_game().await?;
+                    self.place = Place::Leaving;
+                }
+            }
+            packet => {
+                if let Place::Game(game) = &mut self.place {
+                    if let Some((index, stroke)) = game.mirror.handle(&packet) {
+                        let in_hole = game.mirror.stroke(index, stroke);
+                        self.client.end_stroke(index, in_hole).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn play_turn(&mut self, index: usize) -> Result<()> {
+        if self.settings.scenario != Scenario::Play {
+            return Ok(());
+        }
+        let Place::Game(game) = &mut self.place else {
+            return Ok(());
+        };
+        if game.mirror.index != Some(index) {
+            return Ok(());
+        }
+        let stroke = game.choose(index);
+        self.client.stroke(stroke).await?;
+        let in_hole = game.mirror.stroke(index, stroke);
+        self.client.end_stroke(index, in_hole).await?;
+        self.stats.lock().unwrap().strokes += 1;
+        Ok(())
+    }
+}
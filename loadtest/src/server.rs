@@ -0,0 +1,157 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::TcpStream,
+    process::{Child, Command},
+    time::{sleep, Instant},
+};
+
+/// Ticks that took the server longer than a tick may.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Overruns {
+    pub count: u64,
+    pub worst: Duration,
+}
+
+/// A server started for the test. It is killed when this is dropped.
+pub struct ServerProcess {
+    child: Child,
+    /// The server's `TICK_STATS` file.
+    stats_file: PathBuf,
+}
+
+impl ServerProcess {
+    /// Starts the server binary at `path`, keeping its tick overruns in a stats
+    /// file. The server runs in the current directory, so `TRACKS_DIR` and the
+    /// other settings of its environment apply.
+    pub fn spawn(path: &Path, ciphers: bool) -> Result<Self> {
+        let stats_file =
+            std::env::temp_dir().join(format!("loadtest-ticks-{}", std::process::id()));
+        let mut command = Command::new(path);
+        command
+            .stderr(Stdio::piped())
+            .env("TICK_STATS", &stats_file)
+            .kill_on_drop(true);
+        if ciphers {
+            command.env("CIPHERS", "full");
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("failed to start {}", path.display()))?;
+
+        let stderr = child.stderr.take().expect("stderr is piped");
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                log::info!("server: {}", line);
+            }
+        });
+        Ok(Self { child, stats_file })
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// `None` until the server has written its stats file.
+    pub fn overruns(&self) -> Option<Overruns> {
+        let stats = std::fs::read_to_string(&self.stats_file).ok()?;
+        parse_overruns(&stats)
+    }
+
+    /// Waits until the server accepts connections on `addr`.
+    pub async fn wait_ready(&mut self, addr: SocketAddr) -> Result<()> {
+        let give_up = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                bail!("server exited with {}", status);
+            }
+            if TcpStream::connect(addr).await.is_ok() {
+                return Ok(());
+            }
+            if Instant::now() > give_up {
+                bail!("server didn't start listening on {}", addr);
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Whether the server is still running.
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.stats_file);
+    }
+}
+
+/// The `overruns` and `worst_ms` lines the server keeps in its `TICK_STATS` file.
+fn parse_overruns(stats: &str) -> Option<Overruns> {
+    let mut overruns = Overruns::default();
+    for line in stats.lines() {
+        match line.split_once(' ')? {
+            ("overruns", count) => overruns.count = count.parse().ok()?,
+            ("worst_ms", millis) => overruns.worst = Duration::from_millis(millis.parse().ok()?),
+            _ => {}
+        }
+    }
+    Some(overruns)
+}
+
+/// Resident memory of process `pid` in KiB, read from `/proc`.
+pub fn memory(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    parse_rss(&status)
+}
+
+fn parse_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Memory of the server over the test, in KiB.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemoryUsage {
+    pub first: u64,
+    pub last: u64,
+    pub peak: u64,
+}
+
+impl MemoryUsage {
+    pub fn sample(&mut self, kib: u64) {
+        if self.first == 0 {
+            self.first = kib;
+        }
+        self.last = kib;
+        self.peak = self.peak.max(kib);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_overruns, parse_rss};
+
+    #[test]
+    fn parses_server_output() {
+        let overruns = parse_overruns("overruns 3\nworst_ms 212\n").unwrap();
+        assert_eq!(overruns.count, 3);
+        assert_eq!(overruns.worst, Duration::from_millis(212));
+        assert!(parse_overruns("overruns many\n").is_none());
+
+        let status = "Name:\tserver\nVmPeak:\t  20000 kB\nVmRSS:\t    8120 kB\nThreads:\t4\n";
+        assert_eq!(parse_rss(status), Some(8120));
+        assert_eq!(parse_rss("Name:\tserver\n"), None);
+    }
+}
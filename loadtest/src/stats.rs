@@ -0,0 +1,146 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+/// What the simulated clients saw, shared by all of them.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Clients that got through the login.
+    pub connected: usize,
+    /// Clients that never got through the login, by reason.
+    pub failed: HashMap<String, usize>,
+    /// Clients that lost their connection before the test ended, by reason.
+    pub dropped: HashMap<String, usize>,
+    /// Chat messages sent to measure latency.
+    pub sent: usize,
+    /// From sending a chat message to another client receiving it.
+    pub latency: Histogram,
+    /// From sending a private message to yourself to receiving it, in any
+    /// scenario while in the lobby.
+    pub echo: Histogram,
+    /// Games clients entered, created or joined.
+    pub games: usize,
+    /// Games played to the end.
+    pub rounds: usize,
+    pub strokes: usize,
+}
+
+impl Stats {
+    pub fn fail(&mut self, reason: String) {
+        *self.failed.entry(reason).or_default() += 1;
+    }
+
+    pub fn disconnect(&mut self, reason: String) {
+        *self.dropped.entry(reason).or_default() += 1;
+    }
+
+    pub fn online(&self) -> usize {
+        self.connected - self.dropped.values().sum::<usize>()
+    }
+}
+
+/// Width of a histogram bucket.
+const BUCKET: Duration = Duration::from_micros(100);
+/// Buckets of a histogram, longer durations all land in the last one.
+const BUCKETS: usize = 100_000;
+
+/// Durations counted in buckets of 0.1ms up to 10s. Chat latency gets a sample
+/// for every receiver of every message, far too many to keep one by one.
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        let bucket = (duration.as_micros() / BUCKET.as_micros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.max = self.max.max(duration);
+    }
+
+    /// A duration at least `percent` of the samples don't exceed, accurate to a
+    /// bucket.
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((percent / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank && bucket < BUCKETS - 1 {
+                return Some((BUCKET * (bucket as u32 + 1)).min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "no samples");
+        }
+        for (name, percent) in [("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("max", 100.0)] {
+            let value = self.percentile(percent).unwrap_or_default();
+            write!(f, "{} {:.1}ms, ", name, value.as_secs_f64() * 1000.0)?;
+        }
+        write!(f, "{} samples", self.count)
+    }
+}
+
+/// Reasons and how often they happened, the most common first.
+pub fn reasons(reasons: &HashMap<String, usize>) -> Vec<(&str, usize)> {
+    let mut reasons: Vec<_> = reasons
+        .iter()
+        .map(|(reason, count)| (reason.as_str(), *count))
+        .collect();
+    reasons.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    reasons
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Histogram;
+
+    #[test]
+    fn percentiles() {
+        let millis = Duration::from_millis;
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.percentile(50.0), None);
+        assert_eq!(histogram.to_string(), "no samples");
+
+        for ms in (1..=100).rev() {
+            histogram.record(millis(ms));
+        }
+        histogram.record(Duration::from_secs(60));
+        assert!(histogram.to_string().ends_with("101 samples"));
+        assert_eq!(histogram.percentile(0.0), Some(Duration::from_micros(1100)));
+        assert_eq!(
+            histogram.percentile(50.0),
+            Some(Duration::from_micros(51100))
+        );
+        assert_eq!(
+            histogram.percentile(99.0),
+            Some(Duration::from_micros(100100))
+        );
+        assert_eq!(histogram.percentile(100.0), Some(Duration::from_secs(60)));
+
+        let mut histogram = Histogram::default();
+        histogram.record(Duration::from_micros(250));
+        assert_eq!(histogram.percentile(90.0), Some(Duration::from_micros(250)));
+    }
+}
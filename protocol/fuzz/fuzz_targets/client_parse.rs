@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protocol::{client::ClientToServer, common::Parse};
+
+fuzz_target!(|line: &str| {
+    if let Ok((_, packet)) = ClientToServer::parse(line) {
+        packet.as_string();
+    }
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protocol::{
+    client::ClientToServer,
+    codec::MinigolfCodec,
+    crypt::{Ciphers, ConnCipher, GameCipher, CIPHER_MAGIC_DEFAULT},
+};
+
+// The first byte picks the ciphers and where the rest is split into two reads.
+fuzz_target!(|data: &[u8]| {
+    let Some((&control, data)) = data.split_first() else {
+        return;
+    };
+    let mut codec = MinigolfCodec::new();
+    if control & 1 != 0 {
+        codec.set_ciphers(Ciphers::new(
+            Some(GameCipher::new()),
+            Some(ConnCipher::new(CIPHER_MAGIC_DEFAULT, 148153586)),
+        ));
+    }
+    let split = (control as usize >> 1).min(data.len());
+    for chunk in [&data[..split], &data[split..]] {
+        codec.accept(chunk);
+        while let Ok(Some(_)) = codec.next_packet::<ClientToServer>() {}
+    }
+});
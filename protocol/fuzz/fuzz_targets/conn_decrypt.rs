@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protocol::crypt::{ConnCipher, CIPHER_MAGIC_DEFAULT};
+
+fuzz_target!(|input: &str| {
+    ConnCipher::new(CIPHER_MAGIC_DEFAULT, 148153586).decrypt(input);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protocol::crypt::GameCipher;
+
+fuzz_target!(|input: &str| {
+    GameCipher::new().decrypt(input);
+});
@@ -1,5 +1,6 @@
-use darling::FromDeriveInput;
+use darling::{FromDeriveInput, FromField, FromVariant};
 use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
 use quote::quote;
 
 use syn::{parse_macro_input, DeriveInput, Type};
@@ -19,6 +20,202 @@ struct ParseAttribute {
 
     #[darling(default)]
     opt_f: bool,
+    /// Separator between fields instead of `\t`
+    #[darling(default)]
+    sep: Option<char>,
+}
+
+#[derive(Debug, FromField)]
+#[darling(attributes(parse))]
+struct FieldAttribute {
+    /// Separator before this field instead of the struct's
+    #[darling(default)]
+    sep: Option<char>,
+    /// Not part of the packet, filled with `default` or `Default::default()`
+    #[darling(default)]
+    skip: bool,
+    #[darling(default)]
+    default: Option<syn::Path>,
+    /// Parses the field from the rest of the line
+    #[darling(default)]
+    rest: bool,
+    /// Module with `parse(&str) -> IResult<&str, T>` and `as_string(&T) -> String`
+    #[darling(default)]
+    with: Option<syn::Path>,
+}
+
+#[derive(Debug, FromVariant)]
+#[darling(attributes(parse))]
+struct VariantAttribute {
+    /// Catch-all variant for lines no other variant's tag matches
+    #[darling(default)]
+    fallback: bool,
+    /// Tag written before the variant's fields, e.g. a numeric reason code
+    #[darling(default)]
+    tag: Option<String>,
+}
+
+/// Separators and flags shared by all fields of a struct or enum variant.
+struct Layout {
+    delimiter: char,
+    notab: bool,
+    opt_f: bool,
+    /// Separators other than `\t` that end a field value
+    stop_chars: Vec<char>,
+}
+
+struct FieldCode {
+    /// Name of the field or `field_<index>` for tuple fields
+    binding: Ident,
+    /// Parses the field into `binding`
+    parse: TokenStream2,
+    /// Appends the field to `result`, the field is bound to `binding` by reference
+    as_string: TokenStream2,
+    /// Generates a random value into `binding`
+    arbitrary: TokenStream2,
+}
+
+fn field_code(fields: &syn::Fields, layout: &Layout) -> Vec<FieldCode> {
+    let attributes: Vec<FieldAttribute> = fields
+        .iter()
+        .map(|field| FieldAttribute::from_field(field).expect("can't parse field attribute"))
+        .collect();
+    // Separator before each field that is on the wire
+    let separators: Vec<Option<char>> = fields
+        .iter()
+        .zip(&attributes)
+        .map(|(field, attr)| {
+            let on_wire = !is_packet_number_type(&field.ty) && !attr.skip;
+            on_wire.then(|| attr.sep.unwrap_or(layout.delimiter))
+        })
+        .collect();
+
+    let mut wire_index = 0;
+    fields
+        .iter()
+        .zip(attributes)
+        .enumerate()
+        .map(|(i, (field, attr))| {
+            // A value ends at the struct's separator, its own or the one of the next field
+            let next_separator = separators[i + 1..].iter().flatten().next().copied();
+            let mut stop_chars = layout.stop_chars.clone();
+            for sep in attr.sep.into_iter().chain(next_separator) {
+                if sep != '\t' && !stop_chars.contains(&sep) {
+                    stop_chars.push(sep);
+                }
+            }
+
+            let binding = match &field.ident {
+                Some(ident) => ident.clone(),
+                None => Ident::new(&format!("field_{}", i), Span::call_site()),
+            };
+            let field_type = &field.ty;
+
+            let arbitrary = quote! {
+                let #binding = <#field_type as crate::arbitrary::Arbitrary>::arbitrary(g);
+            };
+            if is_packet_number_type(field_type) {
+                return FieldCode {
+                    parse: quote! {},
+                    as_string: quote! {},
+                    arbitrary,
+                    binding,
+                };
+            }
+            if attr.skip {
+                let default = match &attr.default {
+                    Some(path) => quote! { #path() },
+                    None => quote! { Default::default() },
+                };
+                return FieldCode {
+                    parse: quote! { let #binding = #default; },
+                    as_string: quote! {},
+                    arbitrary: quote! { let #binding = #default; },
+                    binding,
+                };
+            }
+
+            let delimiter = attr.sep.unwrap_or(layout.delimiter);
+            let (parse_separator, write_separator) = if wire_index == 0 && layout.notab {
+                (quote! {}, quote! {})
+            } else if layout.opt_f {
+                (
+                    quote! { let (input, _) = opt(char(#delimiter))(input)?; },
+                    quote! {},
+                )
+            } else {
+                (
+                    quote! { let (input, _) = char(#delimiter)(input)?; },
+                    quote! { result.push(#delimiter); },
+                )
+            };
+            wire_index += 1;
+
+            let (parser, writer) = match &attr.with {
+                Some(with) => (
+                    quote! { #with::parse },
+                    quote! { #with::as_string(#binding) },
+                ),
+                None => (
+                    quote! { <#field_type>::parse },
+                    quote! { #binding.as_string() },
+                ),
+            };
+
+            let parse_value = if attr.rest {
+                quote! {
+                    let (input, value) = take_while(|c: char| c != '\n')(input)?;
+                    let (_, #binding) = all_consuming(#parser)(value)?;
+                }
+            } else if !stop_chars.is_empty() {
+                // Values can't tell where they end, cut them at the next separator
+                quote! {
+                    let (input, value) = take_while(|c: char| {
+                        c != '\t' && c != '\n' #(&& c != #stop_chars)*
+                    })(input)?;
+                    let (_, #binding) = all_consuming(#parser)(value)?;
+                }
+            } else {
+                quote! {
+                    let (input, #binding) = #parser(input)?;
+                }
+            };
+
+            FieldCode {
+                parse: quote! {
+                    #parse_separator
+                    #parse_value
+                },
+                as_string: quote! {
+                    #write_separator
+                    result.push_str(&#writer);
+                },
+                arbitrary,
+                binding,
+            }
+        })
+        .collect()
+}
+
+/// Builds `Name { a, b }`, `Name(field_0, field_1)` or `Name` from the field bindings.
+fn construct(path: TokenStream2, fields: &syn::Fields, code: &[FieldCode]) -> TokenStream2 {
+    let bindings = code.iter().map(|field| &field.binding);
+    match fields {
+        syn::Fields::Named(_) => quote! { #path { #(#bindings),* } },
+        syn::Fields::Unnamed(_) => quote! { #path ( #(#bindings),* ) },
+        syn::Fields::Unit => quote! { #path },
+    }
+}
+
+fn uses() -> TokenStream2 {
+    quote! {
+        #[allow(unused_imports)]
+        use nom::bytes::complete::{tag, take_while};
+        #[allow(unused_imports)]
+        use nom::combinator::{all_consuming, opt};
+        #[allow(unused_imports)]
+        use nom::character::complete::char;
+    }
 }
 
 #[proc_macro_derive(Parse, attributes(parse))]
@@ -31,77 +228,34 @@ pub fn parse_derive(input: TokenStream) -> TokenStream {
         notab,
         notag,
         opt_f,
+        sep,
     } = FromDeriveInput::from_derive_input(&input).expect("can't parse attribute");
 
     let item_name = &input.ident;
+    let delimiter = match (sep, space) {
+        (Some(sep), _) => sep,
+        (None, true) => ' ',
+        (None, false) => '\t',
+    };
+    let layout = Layout {
+        delimiter,
+        notab,
+        opt_f,
+        stop_chars: sep.into_iter().filter(|&sep| sep != '\t').collect(),
+    };
+    let uses = uses();
 
     let expanded = match &input.data {
         syn::Data::Struct(data) => {
-            let fields = match &data.fields {
-                syn::Fields::Named(fields) => &fields.named,
-                _ => panic!("Parse can only be used with named fields in structs"),
-            };
-
-            let delimiter = {
-                if space {
-                    ' '
-                } else {
-                    '\t'
-                }
-            };
-
+            let fields = &data.fields;
             let packet_number = fields.iter().any(|f| is_packet_number_type(&f.ty));
+            let code = field_code(fields, &layout);
 
-            let field_parsing = fields
-                .iter()
-                .filter(|f| !is_packet_number_type(&f.ty))
-                .enumerate()
-                .map(|(i, field)| {
-                    let field_name = &field.ident;
-                    let field_type = &field.ty;
-
-                    if i == 0 && notab {
-                        quote! {
-                            let (input, #field_name) = <#field_type>::parse(input)?;
-                        }
-                    } else if opt_f {
-                        quote! {
-                            let (input, _) = opt(char(#delimiter))(input)?;
-                            let (input, #field_name) = <#field_type>::parse(input)?;
-                        }
-                    } else {
-                        quote! {
-                            let (input, _) = char(#delimiter)(input)?;
-                            let (input, #field_name) = <#field_type>::parse(input)?;
-                        }
-                    }
-                });
-
-            let as_string_impl = fields
-                .iter()
-                .filter(|f| !is_packet_number_type(&f.ty))
-                .enumerate()
-                .map(|(i, field)| {
-                    let field_name = &field.ident;
-                    if (i == 0 && notab) || opt_f {
-                        quote! {
-                            result.push_str(&self.#field_name.as_string());
-                        }
-                    } else {
-                        quote! {
-                            result.push(#delimiter);
-                            result.push_str(&self.#field_name.as_string());
-
-                        }
-                    }
-                });
-
-            let field_names = fields.iter().map(|field| {
-                let field_name = &field.ident;
-                quote! {
-                    #field_name,
-                }
-            });
+            let field_parsing = code.iter().map(|field| &field.parse);
+            let as_string_impl = code.iter().map(|field| &field.as_string);
+            let arbitrary_fields = code.iter().map(|field| &field.arbitrary);
+            let construct = construct(quote! { #item_name }, fields, &code);
+            let destructure = construct.clone();
 
             let packet_string = {
                 if packet_number {
@@ -125,6 +279,16 @@ pub fn parse_derive(input: TokenStream) -> TokenStream {
                 }
             };
 
+            let matches_tag = if notag || tag.is_empty() {
+                quote! {}
+            } else {
+                quote! {
+                    fn matches_tag(input: &str) -> bool {
+                        crate::common::starts_with_tag(input, #packet_number, #tag)
+                    }
+                }
+            };
+
             let packet_number_impl = {
                 if packet_number {
                     quote! {
@@ -138,111 +302,245 @@ pub fn parse_derive(input: TokenStream) -> TokenStream {
             };
 
             quote! {
-                impl Packet for #item_name{
-                                fn packet_number(&self) -> Option<PacketNumber>{
-                                    #packet_number_impl
-                                }
-                }
-                            impl Parse for #item_name {
-                                fn parse(input: &str) -> IResult<&str,Self> {
-                                    use nom::bytes::complete::tag;
-                                    use nom::combinator::opt;
-                                    use nom::character::complete::char;
-
-
-                                    let (input, packet_number) = if #packet_number {
-                                        let (input, _) = tag("d ")(input)?;
-                                        let (input, packet_number) = <PacketNumber>::parse(input)?;
-                                        let (input, _) = char(' ')(input)?;
-                                        (input, packet_number)
-                                    } else {
-                                        (input, PacketNumber(0))
-                                    };
-                                    #input_string
-                                    #(
-                                        #field_parsing
-                                    )*
-
-                                    let (input, _) = opt(char('\n'))(input)?;
-
-                                    Ok((input, #item_name { #(#field_names)* }))
-                                }
+            impl Packet for #item_name{
+                            fn packet_number(&self) -> Option<PacketNumber>{
+                                #packet_number_impl
+                            }
+            }
+                        impl Parse for #item_name {
+                            fn parse(input: &str) -> IResult<&str,Self> {
+                                #uses
+
+                                let (input, packet_number) = if #packet_number {
+                                    let (input, _) = tag("d ")(input)?;
+                                    let (input, packet_number) = <PacketNumber>::parse(input)?;
+                                    let (input, _) = char(' ')(input)?;
+                                    (input, packet_number)
+                                } else {
+                                    (input, PacketNumber(0))
+                                };
+                                #input_string
+                                #(
+                                    #field_parsing
+                                )*
+
+                                let (input, _) = opt(char('\n'))(input)?;
+
+                                Ok((input, #construct))
+                            }
 
-                                fn as_string(&self) -> String {
-                                    let mut result = String::new();
-                                    #packet_string
-                                    result.push_str(#tag);
-                                    #(
-                                        #as_string_impl
-                                    )*
-            /**
-                                    if #useless_tab{
-                                        result.push('\t');
-                                    }*/
-                                    if #notag == false{
-                                        result.push('\n');
-                                    }
-                                    result
+                            #[allow(unused_variables)]
+                            fn as_string(&self) -> String {
+                                let #destructure = self;
+                                let mut result = String::new();
+                                #packet_string
+                                result.push_str(#tag);
+                                #(
+                                    #as_string_impl
+                                )*
+                                if #notag == false{
+                                    result.push('\n');
                                 }
+                                result
+                            }
+
+                            #matches_tag
+                        }
 
+                        #[cfg(any(test, feature = "arbitrary"))]
+                        impl crate::arbitrary::Arbitrary for #item_name {
+                            fn arbitrary(g: &mut crate::arbitrary::Gen) -> Self {
+                                #(#arbitrary_fields)*
+                                #construct
                             }
                         }
+                    }
         }
 
         syn::Data::Enum(data) => {
-            let variants = data.variants.iter().map(|variant| {
-                let variant_name = &variant.ident;
-
-                quote! {
-                    map(<#variant_name>::parse, #item_name::#variant_name)
-                }
-            });
-
-            let variants_string_match = data.variants.iter().map(|variant| {
-                let variant_name = &variant.ident;
-
-                quote! {
-                    #item_name::#variant_name(i) => i.as_string(),
-                }
-            });
-            let variants_packet_number_match = data.variants.iter().map(|variant| {
+            let variants: Vec<_> = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let attr = VariantAttribute::from_variant(variant)
+                        .expect("can't parse variant attribute");
+                    (variant, attr)
+                })
+                .collect();
+            let fallbacks: Vec<_> = variants.iter().filter(|(_, attr)| attr.fallback).collect();
+            if fallbacks.len() > 1 {
+                panic!("Parse allows only one #[parse(fallback)] variant");
+            }
+            let fallback = fallbacks.first().map(|(variant, _)| &variant.ident);
+
+            let mut parsers = Vec::new();
+            // Whether the line is the packet of the parser at the same index
+            let mut tag_checks = Vec::new();
+            let mut variant_fns = Vec::new();
+            let mut arbitrary_arms = Vec::new();
+            let mut string_match = Vec::new();
+            let mut packet_number_match = Vec::new();
+            for (variant, attr) in &variants {
                 let variant_name = &variant.ident;
-
-                quote! {
-                    #item_name::#variant_name(i) => i.packet_number(),
+                match &attr.tag {
+                    // A tag followed by the variant's own fields
+                    Some(variant_tag) => {
+                        let code = field_code(&variant.fields, &layout);
+                        let field_parsing = code.iter().map(|field| &field.parse);
+                        let as_string_impl = code.iter().map(|field| &field.as_string);
+                        let arbitrary_fields = code.iter().map(|field| &field.arbitrary);
+                        let construct =
+                            construct(quote! { #item_name::#variant_name }, &variant.fields, &code);
+                        if !attr.fallback {
+                            arbitrary_arms.push(quote! {
+                                #(#arbitrary_fields)*
+                                #construct
+                            });
+                        }
+                        if !attr.fallback {
+                            let parser = Ident::new(
+                                &format!("parse_{}", variant_name).to_lowercase(),
+                                Span::call_site(),
+                            );
+                            variant_fns.push(quote! {
+                                fn #parser(input: &str) -> IResult<&str, #item_name> {
+                                    #uses
+                                    let (input, _) = tag(#variant_tag)(input)?;
+                                    #(#field_parsing)*
+                                    Ok((input, #construct))
+                                }
+                            });
+                            parsers.push(quote! { #parser });
+                            tag_checks.push(quote! {
+                                |input: &str| crate::common::starts_with_tag(input, false, #variant_tag)
+                            });
+                        }
+                        string_match.push(quote! {
+                            #construct => {
+                                let mut result = String::from(#variant_tag);
+                                #(#as_string_impl)*
+                                result
+                            }
+                        });
+                        let pattern = match &variant.fields {
+                            syn::Fields::Named(_) => quote! { #item_name::#variant_name { .. } },
+                            syn::Fields::Unnamed(_) => quote! { #item_name::#variant_name(..) },
+                            syn::Fields::Unit => quote! { #item_name::#variant_name },
+                        };
+                        packet_number_match.push(quote! { #pattern => None, });
+                    }
+                    // A packet wrapped in a newtype variant
+                    None => {
+                        if !attr.fallback {
+                            parsers.push(quote! {
+                                map(<#variant_name>::parse, #item_name::#variant_name)
+                            });
+                            tag_checks.push(quote! { <#variant_name>::matches_tag });
+                            arbitrary_arms.push(quote! {
+                                #item_name::#variant_name(crate::arbitrary::Arbitrary::arbitrary(g))
+                            });
+                        }
+                        string_match.push(quote! {
+                            #item_name::#variant_name(i) => i.as_string(),
+                        });
+                        packet_number_match.push(quote! {
+                            #item_name::#variant_name(i) => i.packet_number(),
+                        });
+                    }
                 }
-            });
-            let chunks: Vec<_> = variants.clone().collect();
+            }
 
-            let alt_variants = chunks.chunks(21).map(|chunk| {
-                let alt_variants = chunk.to_owned();
-                quote! {
-                    alt((#(#alt_variants),*))
+            let variant_count = arbitrary_arms.len();
+            let arbitrary_indices = 0..variant_count;
+            let parse_impl = match fallback {
+                // Every variant is tried for one consuming the whole line. A line whose
+                // tag matched is that packet even if its fields don't parse, only the
+                // rest falls back.
+                Some(fallback) => quote! {
+                    let variants: &[(fn(&str) -> IResult<&str, #item_name>, fn(&str) -> bool)] = &[
+                        #((|input: &str| #parsers(input), #tag_checks)),*
+                    ];
+                    let mut partial = None;
+                    let mut failure = None;
+                    for (parser, matches_tag) in variants {
+                        match parser(input) {
+                            Ok((rest, command))
+                                if rest.is_empty() || input[..input.len() - rest.len()].ends_with('\n') =>
+                            {
+                                return Ok((rest, command));
+                            }
+                            Ok(result) => {
+                                if partial.is_none() && matches_tag(input) {
+                                    partial = Some(result);
+                                }
+                            }
+                            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                                if failure.is_none() && matches_tag(input) {
+                                    failure = Some(e);
+                                }
+                            }
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    match (partial, failure) {
+                        (Some(result), _) => Ok(result),
+                        (None, Some(e)) => Err(nom::Err::Failure(e)),
+                        (None, None) => map(<#fallback>::parse, #item_name::#fallback)(input),
+                    }
+                },
+                None => {
+                    let alt_variants: Vec<_> = parsers
+                        .chunks(21)
+                        .map(|chunk| match chunk {
+                            [parser] => quote! { #parser },
+                            _ => quote! { alt((#(#chunk),*)) },
+                        })
+                        .collect();
+                    // alt takes tuples of 2 to 21 parsers
+                    let alt_variants = match alt_variants.as_slice() {
+                        [parser] => quote! { #parser },
+                        _ => quote! { alt((#(#alt_variants),*)) },
+                    };
+                    quote! {
+                        let (input,command) = #alt_variants(input)?;
+                        Ok((input,command))
+                    }
                 }
-            });
+            };
 
             quote! {
             impl Parse for #item_name {
                 fn parse(input: &str) -> IResult<&str,Self> {
+                    #[allow(unused_imports)]
                     use nom::branch::alt;
+                    #[allow(unused_imports)]
                     use nom::combinator::map;
+                    #(#variant_fns)*
 
-                    let (input,command) = alt((#(#alt_variants),*))(input)?;
-                    Ok((input,command))
+                    #parse_impl
                     }
+                #[allow(unused_variables)]
                 fn as_string(&self) -> String{
-                    match &self{
-                        #(#variants_string_match)*
-                        _ => unreachable!()
+                    match self{
+                        #(#string_match)*
                     }
                 }
             }
             impl Packet for #item_name {
 
                 fn packet_number(&self) -> Option<PacketNumber>{
-                    match &self{
-                        #(#variants_packet_number_match)*
-                        _ => unreachable!()
+                    match self{
+                        #(#packet_number_match)*
+                    }
+                }
+            }
+
+            #[cfg(any(test, feature = "arbitrary"))]
+            impl crate::arbitrary::Arbitrary for #item_name {
+                fn arbitrary(g: &mut crate::arbitrary::Gen) -> Self {
+                    match g.variant(#variant_count) {
+                        #(#arbitrary_indices => { #arbitrary_arms })*
+                        _ => unreachable!(),
                     }
                 }
             }
@@ -252,14 +550,14 @@ pub fn parse_derive(input: TokenStream) -> TokenStream {
         _ => panic!("Parse can only be used with enums or structs"),
     };
 
-    fn is_packet_number_type(ty: &Type) -> bool {
-        if let Type::Path(type_path) = ty {
-            if let Some(ident) = type_path.path.get_ident() {
-                return ident == "PacketNumber";
-            }
+    expanded.into()
+}
+
+fn is_packet_number_type(ty: &Type) -> bool {
+    if let Type::Path(type_path) = ty {
+        if let Some(ident) = type_path.path.get_ident() {
+            return ident == "PacketNumber";
         }
-        false
     }
-
-    expanded.into()
+    false
 }
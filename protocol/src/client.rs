@@ -7,11 +7,14 @@ use parsemacro::Parse as ParseD;
 
 use crate::common::Packet;
 use crate::common::PacketNumber;
+use crate::common::Unknown;
+use crate::stroke::Stroke;
 use nom::IResult;
 
 //D
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "version")]
 pub struct Version {
     pub packet_number: PacketNumber,
@@ -19,6 +22,7 @@ pub struct Version {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "language")]
 pub struct Language {
     pub packet_number: PacketNumber,
@@ -26,6 +30,7 @@ pub struct Language {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "logintype")]
 pub struct LoginType {
     pub packet_number: PacketNumber,
@@ -33,12 +38,14 @@ pub struct LoginType {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "login")]
 pub struct Login {
     pub packet_number: PacketNumber,
     pub session: Option<i32>,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "ttlogin")]
 pub struct TTLogin {
     pub packet_number: PacketNumber,
@@ -47,6 +54,7 @@ pub struct TTLogin {
 }
 //D
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "quit")]
 pub struct Quit {
     pub packet_number: PacketNumber,
@@ -55,12 +63,14 @@ pub struct Quit {
 // D LOBBYSELECT
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobbyselect\trnop")] //request number of players
 pub struct LobbySelectRnop {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobbyselect\tcspt")]
 pub struct LobbySelectCspt {
     pub packet_number: PacketNumber,
@@ -69,12 +79,14 @@ pub struct LobbySelectCspt {
     pub water_event: WaterEvent,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobbyselect\tqmpt")]
 pub struct LobbySelectQmpt {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobbyselect\tselect")]
 pub struct LobbySelectSelect {
     pub packet_number: PacketNumber,
@@ -83,24 +95,35 @@ pub struct LobbySelectSelect {
 //LOBBY
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tback")]
 pub struct LobbyBack {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tselect")]
 pub struct LobbySelect {
     pub packet_number: PacketNumber,
     pub lobby_type: DLobbyType, //DLobbyType,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\ttracksetlist")]
 pub struct LobbyTrackSetlist {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[parse(tag = "lobby\ttracklist")]
+pub struct LobbyTracklist {
+    pub packet_number: PacketNumber,
+}
+
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tcspt")]
 pub struct LobbyCspt {
     pub packet_number: PacketNumber,
@@ -110,6 +133,7 @@ pub struct LobbyCspt {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tcmpt")]
 pub struct LobbyCmpt {
     pub packet_number: PacketNumber,
@@ -128,6 +152,7 @@ pub struct LobbyCmpt {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tsay")]
 pub struct LobbySay {
     pub packet_number: PacketNumber,
@@ -135,12 +160,14 @@ pub struct LobbySay {
     pub message: String,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tnc")]
 pub struct LobbyNc {
     pub packet_number: PacketNumber,
     pub no_challenges: bool,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tcfail")]
 pub struct LobbyCFail {
     pub packet_number: PacketNumber,
@@ -149,6 +176,7 @@ pub struct LobbyCFail {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tsayp")]
 pub struct LobbySayP {
     pub packet_number: PacketNumber,
@@ -157,30 +185,35 @@ pub struct LobbySayP {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tjmpt")]
 pub struct LobbyJmpt {
     pub packet_number: PacketNumber,
     pub network_id: usize,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tcspc")]
 pub struct LobbyCspc {
     pub packet_number: PacketNumber,
     pub network_id: usize,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tcancel")]
 pub struct LobbyCancel {
     pub packet_number: PacketNumber,
     pub challenged: String,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\taccept")]
 pub struct LobbyAccept {
     pub packet_number: PacketNumber,
     pub challenger: String,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tchallenge")]
 pub struct LobbyChallenge {
     pub packet_number: PacketNumber,
@@ -196,6 +229,7 @@ pub struct LobbyChallenge {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tquit")]
 pub struct LobbyQuit {
     pub packet_number: PacketNumber,
@@ -203,6 +237,7 @@ pub struct LobbyQuit {
 //D GAME
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\trate")]
 pub struct GameRate {
     pub packet_number: PacketNumber,
@@ -211,43 +246,49 @@ pub struct GameRate {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tstartturn")]
 pub struct GameStartTurn {
     pub packet_number: PacketNumber,
     pub id: i32,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tbeginstroke")]
 pub struct GameBeginStroke {
     pub packet_number: PacketNumber,
-    pub coords: String,
+    pub coords: Stroke,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tendstroke")]
 pub struct GameEndStroke {
     pub packet_number: PacketNumber,
     pub index: usize,
-    //pub in_hole: PlayerInfo,
-    pub in_hole: String,
+    pub in_hole: PlayerInfo,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tskip")]
 pub struct GameSkip {
     pub packet_number: PacketNumber,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tnewgame")]
 pub struct GameNewGame {
     pub packet_number: PacketNumber,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tbacktoprivate")]
 pub struct GameBackToPrivate {
     pub packet_number: PacketNumber,
     pub value_1: i32,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\trejectaccept")]
 pub struct GameRejectAccept {
     pub packet_number: PacketNumber,
@@ -255,12 +296,14 @@ pub struct GameRejectAccept {
     pub value: bool,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tvoteskip")]
 pub struct GameVoteSkip {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tjoin")]
 pub struct GameJoin {
     pub packet_number: PacketNumber,
@@ -268,6 +311,7 @@ pub struct GameJoin {
     pub username: String,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tsay")]
 pub struct GameSay {
     pub packet_number: PacketNumber,
@@ -275,36 +319,47 @@ pub struct GameSay {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tback")]
 pub struct GameBack {
     pub packet_number: PacketNumber,
 }
 // S
 
+/// A log entry of the client, usually an error it ran into. Sent at any time,
+/// even in the middle of the login handshake.
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "s tlog")]
 pub struct TLog {
+    /// Counts up for every log the client sends.
     pub count: i32,
     pub id: String,
-    pub str: String,
-    //pub log: Vec<String>,
+    /// The message, one field per line of it. Fields may be empty.
+    #[parse(rest)]
+    pub log: Vec<String>,
 }
 
 // C
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c new", space = true)]
 pub struct New {}
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c old", space = true)]
 pub struct Old {
     pub id: i32,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c pong")]
 pub struct Pong {}
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "data"))]
 pub enum ClientToServer {
     Version(Version),
     Language(Language),
@@ -323,6 +378,7 @@ pub enum ClientToServer {
     LobbySelect(LobbySelect),
     LobbyChallenge(LobbyChallenge),
     LobbyTrackSetlist(LobbyTrackSetlist),
+    LobbyTracklist(LobbyTracklist),
     LobbyCmpt(LobbyCmpt),
     LobbySay(LobbySay),
     LobbyCFail(LobbyCFail),
@@ -347,4 +403,47 @@ pub enum ClientToServer {
     New(New),
     Old(Old),
     Pong(Pong),
+    #[parse(fallback)]
+    Unknown(Unknown),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches;
+
+    use super::ClientToServer;
+    use crate::{
+        arbitrary::assert_roundtrip,
+        common::{
+            Parse,
+            PlayerState::{InHole, Left, NotInHole},
+        },
+    };
+
+    #[test]
+    fn roundtrip_test() {
+        assert_roundtrip::<ClientToServer>(10_000);
+    }
+
+    #[test]
+    fn endstroke_test() {
+        let packet = ClientToServer::parse("d 6 game\tendstroke\t1\tftp\n")
+            .unwrap()
+            .1;
+        assert_matches!(packet, ClientToServer::GameEndStroke(end) if end.in_hole.0 == [NotInHole, InHole, Left]);
+
+        let result = ClientToServer::parse("d 6 game\tendstroke\t1\tfxf\n");
+        assert_matches!(result, Err(nom::Err::Failure(_)));
+    }
+
+    #[test]
+    fn tlog_test() {
+        let input = "s tlog\t2\terror\tjava.lang.NullPointerException\t\tat Game.run\n";
+        let packet = ClientToServer::parse(input).unwrap().1;
+        assert_matches!(&packet, ClientToServer::TLog(log) if log.count == 2 && log.log.len() == 3);
+        assert_eq!(packet.as_string(), input);
+
+        let packet = ClientToServer::parse("s tlog\t1\tid\tstr\n").unwrap().1;
+        assert_matches!(packet, ClientToServer::TLog(log) if log.log == ["str"]);
+    }
 }
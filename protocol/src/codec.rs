@@ -0,0 +1,263 @@
+use anyhow::bail;
+use bytes::BytesMut;
+
+use crate::{common::Parse, crypt::Ciphers};
+
+/// Longest line a client may send, including the `\n`.
+pub const MAX_LINE_LENGTH: usize = 8192;
+
+/// Result of decoding one complete line.
+#[derive(Debug)]
+pub enum Decoded<T> {
+    Packet(T),
+    /// No packet matches the line. It was skipped.
+    Unknown(String),
+    /// The line is not UTF-8, cannot be decrypted, has data left over after the packet
+    /// or is a known packet whose fields don't parse.
+    Malformed {
+        line: String,
+        reason: String,
+    },
+}
+
+pub struct MinigolfCodec {
+    received_buf: BytesMut,
+    encrypted_buf: BytesMut,
+    ciphers: Ciphers,
+    max_line_length: usize,
+}
+
+impl Default for MinigolfCodec {
+    fn default() -> Self {
+        Self::with_max_line_length(MAX_LINE_LENGTH)
+    }
+}
+
+impl MinigolfCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A codec that accepts lines up to `max_line_length` bytes, for reading
+    /// the server, whose lines aren't bound by [`MAX_LINE_LENGTH`].
+    pub fn with_max_line_length(max_line_length: usize) -> Self {
+        Self {
+            received_buf: BytesMut::new(),
+            encrypted_buf: BytesMut::new(),
+            ciphers: Ciphers::none(),
+            max_line_length,
+        }
+    }
+
+    /// Ciphers for everything accepted from now on.
+    pub fn set_ciphers(&mut self, ciphers: Ciphers) {
+        self.ciphers = ciphers;
+    }
+
+    pub fn accept(&mut self, bytes: &[u8]) {
+        if self.ciphers.is_none() {
+            self.received_buf.extend(bytes);
+        } else {
+            // Encrypted lines can only be decrypted once they are complete
+            self.encrypted_buf.extend(bytes);
+        }
+    }
+
+    /// Decodes the next complete line. `Ok(None)` means more bytes are needed, an
+    /// error means the other side has sent a line longer than the codec accepts.
+    pub fn next_packet<T>(&mut self) -> anyhow::Result<Option<Decoded<T>>>
+    where
+        T: Parse,
+    {
+        // Plaintext received before the ciphers were enabled comes first
+        if let Some(bytes) = take_line(&mut self.received_buf, self.max_line_length)? {
+            return Ok(Some(decode(&bytes)));
+        }
+        let Some(bytes) = take_line(&mut self.encrypted_buf, self.max_line_length)? else {
+            return Ok(None);
+        };
+
+        let line = String::from_utf8_lossy(&bytes[..bytes.len() - 1]);
+        match self.ciphers.decrypt_line(&line) {
+            Some(mut line) => {
+                line.push('\n');
+                Ok(Some(decode(line.as_bytes())))
+            }
+            None => Ok(Some(Decoded::Malformed {
+                line: line.into_owned(),
+                reason: "cannot decrypt".to_string(),
+            })),
+        }
+    }
+}
+
+/// Splits the next line including its `\n` off `buf`.
+fn take_line(buf: &mut BytesMut, max_length: usize) -> anyhow::Result<Option<BytesMut>> {
+    let Some(end) = buf.iter().position(|&b| b == b'\n') else {
+        if buf.len() > max_length {
+            bail!("line longer than {} bytes", max_length);
+        }
+        return Ok(None);
+    };
+    if end >= max_length {
+        bail!("line longer than {} bytes", max_length);
+    }
+    Ok(Some(buf.split_to(end + 1)))
+}
+
+fn decode<T: Parse>(bytes: &[u8]) -> Decoded<T> {
+    let line = match std::str::from_utf8(bytes) {
+        Ok(line) => line,
+        Err(e) => {
+            return Decoded::Malformed {
+                line: String::from_utf8_lossy(bytes).into_owned(),
+                reason: e.to_string(),
+            }
+        }
+    };
+
+    match <T>::parse(line) {
+        Ok(("", packet)) => Decoded::Packet(packet),
+        Ok((rest, _)) => Decoded::Malformed {
+            line: line.to_string(),
+            reason: format!("trailing data {:?}", rest),
+        },
+        Err(nom::Err::Failure(e)) => Decoded::Malformed {
+            line: line.to_string(),
+            reason: format!("cannot parse {:?}", e.input),
+        },
+        Err(_) => Decoded::Unknown(line.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client::{ClientToServer, New, Version},
+        common::Parse,
+        crypt::{Ciphers, ConnCipher, GameCipher, CIPHER_MAGIC_DEFAULT},
+    };
+
+    use super::{Decoded, MinigolfCodec, MAX_LINE_LENGTH};
+
+    #[test]
+    fn split_packets() {
+        let mut codec = MinigolfCodec::new();
+        assert!(codec.next_packet::<New>().unwrap().is_none());
+
+        codec.accept(b"c ne");
+        assert!(codec.next_packet::<New>().unwrap().is_none());
+        codec.accept(b"w\nd 1 version\t35\nd 2 vers");
+        assert!(matches!(
+            codec.next_packet::<New>().unwrap(),
+            Some(Decoded::Packet(New {}))
+        ));
+        assert!(matches!(
+            codec.next_packet::<Version>().unwrap(),
+            Some(Decoded::Packet(Version { version: 35, .. }))
+        ));
+        assert!(codec.next_packet::<Version>().unwrap().is_none());
+    }
+
+    #[test]
+    fn partial_utf8() {
+        let mut codec = MinigolfCodec::new();
+        let line = "d 4 lobby\tsay\tl\täö\n".as_bytes();
+        let split = line.len() - 3; // in the middle of 'ö'
+        codec.accept(&line[..split]);
+        assert!(codec.next_packet::<ClientToServer>().unwrap().is_none());
+        codec.accept(&line[split..]);
+        assert!(matches!(
+            codec.next_packet::<ClientToServer>().unwrap(),
+            Some(Decoded::Packet(ClientToServer::LobbySay(_)))
+        ));
+    }
+
+    #[test]
+    fn unknown_and_malformed() {
+        let mut codec = MinigolfCodec::new();
+        codec.accept(b"d 3 what\tis\tthis\n\xff\xfe\nc new extra\nc new\n");
+        assert!(matches!(
+            codec.next_packet::<New>().unwrap(),
+            Some(Decoded::Unknown(line)) if line == "d 3 what\tis\tthis\n"
+        ));
+        assert!(matches!(
+            codec.next_packet::<New>().unwrap(),
+            Some(Decoded::Malformed { .. })
+        ));
+        assert!(matches!(
+            codec.next_packet::<New>().unwrap(),
+            Some(Decoded::Malformed { .. })
+        ));
+        assert!(matches!(
+            codec.next_packet::<New>().unwrap(),
+            Some(Decoded::Packet(New {}))
+        ));
+    }
+
+    #[test]
+    fn unknown_client_packet() {
+        let mut codec = MinigolfCodec::new();
+        codec.accept(b"d 3 what\tis\tthis\n");
+        match codec.next_packet::<ClientToServer>().unwrap() {
+            Some(Decoded::Packet(ClientToServer::Unknown(unknown))) => {
+                assert_eq!(unknown.as_string(), "d 3 what\tis\tthis\n")
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_client_packet() {
+        let mut codec = MinigolfCodec::new();
+        codec.accept(b"d 4 game\tbeginstroke\tzz\nc new extra\n");
+        for line in ["d 4 game\tbeginstroke\tzz\n", "c new extra\n"] {
+            assert!(matches!(
+                codec.next_packet::<ClientToServer>().unwrap(),
+                Some(Decoded::Malformed { line: malformed, .. }) if malformed == line
+            ));
+        }
+    }
+
+    #[test]
+    fn line_too_long() {
+        let mut codec = MinigolfCodec::new();
+        codec.accept(&vec![b'a'; MAX_LINE_LENGTH + 1]);
+        assert!(codec.next_packet::<ClientToServer>().is_err());
+
+        let mut codec = MinigolfCodec::with_max_line_length(MAX_LINE_LENGTH * 2);
+        codec.accept(&vec![b'a'; MAX_LINE_LENGTH + 1]);
+        codec.accept(b"\n");
+        assert!(matches!(codec.next_packet::<ClientToServer>(), Ok(Some(_))));
+    }
+
+    #[test]
+    fn encrypted_lines() {
+        let ciphers = Ciphers::new(
+            Some(GameCipher::new()),
+            Some(ConnCipher::new(CIPHER_MAGIC_DEFAULT, 148153586)),
+        );
+        let mut codec = MinigolfCodec::new();
+        codec.accept(b"c new\n");
+        codec.set_ciphers(ciphers.clone());
+        codec.accept(ciphers.encrypt("d 1 version\t35\n").as_bytes());
+        codec.accept(b"x\n");
+
+        assert!(matches!(
+            codec.next_packet::<ClientToServer>().unwrap(),
+            Some(Decoded::Packet(ClientToServer::New(_)))
+        ));
+        assert!(matches!(
+            codec.next_packet::<ClientToServer>().unwrap(),
+            Some(Decoded::Packet(ClientToServer::Version(Version {
+                version: 35,
+                ..
+            })))
+        ));
+        assert!(matches!(
+            codec.next_packet::<ClientToServer>().unwrap(),
+            Some(Decoded::Malformed { line, .. }) if line == "x"
+        ));
+        assert!(codec.next_packet::<ClientToServer>().unwrap().is_none());
+    }
+}
@@ -15,8 +15,10 @@ use nom::error::make_error;
 use nom::error::ErrorKind;
 
 use nom::multi::separated_list1;
+use nom::sequence::preceded;
 use nom::Err as NomErr;
 use nom::IResult;
+use parsemacro::Parse as ParseD;
 use std::fmt::Display;
 
 pub fn nom_error_to_anyhow(err: NomErr<nom::error::Error<&str>>) -> anyhow::Error {
@@ -25,7 +27,8 @@ pub fn nom_error_to_anyhow(err: NomErr<nom::error::Error<&str>>) -> anyhow::Erro
         NomErr::Error(e) | NomErr::Failure(e) => anyhow::Error::msg(format!("Error: {}", e)),
     }
 }
-#[derive(Debug, PartialEq, Default)]
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KickStyle {
     #[default]
     KickNow,
@@ -35,6 +38,7 @@ pub enum KickStyle {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DLobbyType {
     #[default]
     Solo,
@@ -99,6 +103,7 @@ macro_rules! impl_from_str_enum {
     ($enum_name:ident { $($variant:ident),* }) => {
 
         #[derive(Debug, PartialEq, Copy, Clone, Default)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum $enum_name{
             #[default]
             $(
@@ -130,6 +135,14 @@ macro_rules! impl_from_str_enum {
                 }
             }
         }
+
+        #[cfg(any(test, feature = "arbitrary"))]
+        impl crate::arbitrary::Arbitrary for $enum_name {
+            fn arbitrary(g: &mut crate::arbitrary::Gen) -> Self {
+                let variants = [$($enum_name::$variant,)*];
+                variants[g.variant(variants.len())]
+            }
+        }
     };
 }
 
@@ -143,18 +156,53 @@ impl_from_str_enum!(DChallengeFail {
     COther
 });
 
-impl_from_str_enum!(DLoginStatus {
+/// Why `status\tlogin` refused a login.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DLoginStatus {
+    #[default]
     NickInUse,
     Rlf,
     InvalidNick,
-    ForbiddenNick
-});
+    ForbiddenNick,
+    /// A status without a name here, kept as sent so the line still parses.
+    Other(String),
+}
+
+impl Display for DLoginStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DLoginStatus::NickInUse => write!(f, "nickinuse"),
+            DLoginStatus::Rlf => write!(f, "rlf"),
+            DLoginStatus::InvalidNick => write!(f, "invalidnick"),
+            DLoginStatus::ForbiddenNick => write!(f, "forbiddennick"),
+            DLoginStatus::Other(status) => write!(f, "{}", status),
+        }
+    }
+}
+
+impl std::str::FromStr for DLoginStatus {
+    type Err = MyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err(MyParseError),
+            "nickinuse" => Ok(DLoginStatus::NickInUse),
+            "rlf" => Ok(DLoginStatus::Rlf),
+            "invalidnick" => Ok(DLoginStatus::InvalidNick),
+            "forbiddennick" => Ok(DLoginStatus::ForbiddenNick),
+            other => Ok(DLoginStatus::Other(other.to_string())),
+        }
+    }
+}
 
 impl_from_str_enum!(DErrorType {
     VerNotOk,
     ServerFull
 });
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct PacketNumber(pub u32);
 
 impl fmt::Display for PacketNumber {
@@ -177,6 +225,71 @@ impl Parse for PacketNumber {
     }
 }
 
+/// A line none of the known packets matched. `as_string` gives it back byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Unknown {
+    pub packet_number: Option<PacketNumber>,
+    /// Everything before the first tab, e.g. `lobby` or `c ping`.
+    pub tag: String,
+    pub fields: Vec<String>,
+}
+
+impl Parse for Unknown {
+    fn parse(input: &str) -> IResult<&str, Self> {
+        if input.is_empty() {
+            return Err(NomErr::Error(make_error(input, ErrorKind::Eof)));
+        }
+        let (input, line) = take_while(|c: char| c != '\n')(input)?;
+        let (input, _) = opt(char('\n'))(input)?;
+
+        // Only take the number if writing it back gives the same text
+        let numbered = line.strip_prefix("d ").and_then(|rest| {
+            let (number, rest) = rest.split_once(' ')?;
+            let packet_number = number.parse::<u32>().ok()?;
+            (packet_number.to_string() == number).then_some((PacketNumber(packet_number), rest))
+        });
+        let (packet_number, line) = match numbered {
+            Some((packet_number, rest)) => (Some(packet_number), rest),
+            None => (None, line),
+        };
+
+        let mut parts = line.split('\t');
+        let tag = parts.next().unwrap_or_default().to_string();
+        let fields = parts.map(str::to_string).collect();
+        Ok((
+            input,
+            Unknown {
+                packet_number,
+                tag,
+                fields,
+            },
+        ))
+    }
+
+    fn as_string(&self) -> String {
+        let mut result = String::new();
+        if let Some(packet_number) = self.packet_number {
+            result.push_str("d ");
+            result.push_str(&packet_number.as_string());
+            result.push(' ');
+        }
+        result.push_str(&self.tag);
+        for field in &self.fields {
+            result.push('\t');
+            result.push_str(field);
+        }
+        result.push('\n');
+        result
+    }
+}
+
+impl Packet for Unknown {
+    fn packet_number(&self) -> Option<PacketNumber> {
+        self.packet_number
+    }
+}
+
 impl Parse for bool {
     fn parse(input: &str) -> IResult<&str, Self> {
         let (input, key) = alt((char('f'), char('t')))(input)?;
@@ -197,6 +310,33 @@ pub trait Parse {
         Self: Sized;
 
     fn as_string(&self) -> String;
+
+    /// Whether `input` is this packet by its tag, even if the fields don't parse.
+    /// Only packets with a tag of their own ever match.
+    fn matches_tag(_input: &str) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+}
+
+/// Whether `input` starts with `tag` as a whole word, after `d <number> ` for
+/// numbered packets. `lobby\tsay` matches `lobby\tsay\thi` but not `lobby\tsayp`.
+pub fn starts_with_tag(input: &str, numbered: bool, tag: &str) -> bool {
+    let input = match numbered {
+        true => match input
+            .strip_prefix("d ")
+            .and_then(|rest| rest.split_once(' '))
+        {
+            Some((number, rest)) if number.parse::<u32>().is_ok() => rest,
+            _ => return false,
+        },
+        false => input,
+    };
+    input
+        .strip_prefix(tag)
+        .is_some_and(|rest| matches!(rest.chars().next(), None | Some('\t' | '\n' | ' ')))
 }
 
 pub trait Packet {
@@ -232,7 +372,8 @@ impl_parse!(KickStyle);
 
 macro_rules! impl_parse_from_enum {
     ($enum_name:ident { $($value:expr => $variant:ident),* }) => {
-        #[derive(Debug,PartialEq, Copy, Clone,Default)]
+        #[derive(Debug,PartialEq, Eq, Hash, Copy, Clone,Default)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum $enum_name{
             #[default]
             $($variant,)*
@@ -254,6 +395,14 @@ macro_rules! impl_parse_from_enum {
             }
 
         }
+
+        #[cfg(any(test, feature = "arbitrary"))]
+        impl crate::arbitrary::Arbitrary for $enum_name {
+            fn arbitrary(g: &mut crate::arbitrary::Gen) -> Self {
+                let variants = [$($enum_name::$variant,)*];
+                variants[g.variant(variants.len())]
+            }
+        }
     };
 }
 
@@ -293,20 +442,18 @@ impl_parse_from_enum!(TrackType {
     5 => Short,
     6 => Long
 });
-/*
-impl_parse_from_enum!(JoinLeaveReason {
-    1 => StartedSP,
-    2 => CreatedMP,
-    3 => JoinedMP,
-    4 => LeftLobby,
-    5 => LostConnection
-});*/
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct SomeAsTab<T>(pub Option<T>);
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct NoneAsTab<T>(pub Option<T>); //None == \t
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct NonEmptyOption<T>(pub Option<T>); //None == -
 
 impl<T: Parse> Parse for NonEmptyOption<T> {
@@ -349,8 +496,10 @@ impl<T: Parse> Parse for NoneAsTab<T> {
 impl<T: Parse> Parse for SomeAsTab<T> {
     fn parse(input: &str) -> IResult<&str, Self> {
         alt((
+            map(preceded(char('\t'), T::parse), |f| SomeAsTab(Some(f))),
             map(char('\t'), |_| SomeAsTab(None)),
             map(char('\n'), |_| SomeAsTab(None)),
+            // Optional separators of the struct already took the tab
             map(T::parse, |f| SomeAsTab(Some(f))),
         ))(input)
     }
@@ -402,154 +551,406 @@ impl<T: Parse> Parse for Option<T> {
     }
 }
 
-#[derive(Debug)]
-pub struct User {
-    pub id_username: String,
-    pub value_1: String, //unkown
-    pub rank: i32,
-    pub lang: String,
-    pub value_2: NonEmptyOption<String>,
-    pub value_3: NonEmptyOption<String>,
+/// Badges of a user in the lobby user list, letters in the order `wrvsn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct UserFlags(u8);
+
+impl UserFlags {
+    /// `w`, a guest without an account.
+    pub const WORM: UserFlags = UserFlags(1 << 0);
+    /// `r`
+    pub const REGISTERED: UserFlags = UserFlags(1 << 1);
+    /// `v`
+    pub const VIP: UserFlags = UserFlags(1 << 2);
+    /// `s`
+    pub const SHERIFF: UserFlags = UserFlags(1 << 3);
+    /// `n`, the user does not accept challenges.
+    pub const NO_CHALLENGES: UserFlags = UserFlags(1 << 4);
+
+    const LETTERS: [(char, UserFlags); 5] = [
+        ('w', UserFlags::WORM),
+        ('r', UserFlags::REGISTERED),
+        ('v', UserFlags::VIP),
+        ('s', UserFlags::SHERIFF),
+        ('n', UserFlags::NO_CHALLENGES),
+    ];
+
+    pub fn empty() -> Self {
+        UserFlags(0)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(&self, other: UserFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, other: UserFlags, value: bool) {
+        if value {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+
+    fn from_letter(letter: char) -> Option<UserFlags> {
+        UserFlags::LETTERS
+            .iter()
+            .find(|(c, _)| *c == letter)
+            .map(|(_, flag)| *flag)
+    }
 }
 
-impl Parse for User {
-    fn parse(input: &str) -> IResult<&str, Self>
-    where
-        Self: Sized,
-    {
-        let mut parse = map_res(
-            take_while(|c: char| c != '\t' && c != '^' && c != '\n'),
-            str::parse,
-        );
-        let mut parse1 = map_res(
-            take_while(|c: char| c != '\t' && c != '^' && c != '\n'),
-            i32::parse,
-        );
-        let mut parse2 = map_res(
-            take_while(|c: char| c != '\t' && c != '^' && c != '\n'),
-            NonEmptyOption::parse,
-        );
-        let (input, id_username) = parse(input)?;
-        let (input, _) = char('^')(input)?;
-        let (input, value_1) = parse(input)?;
-        let (input, _) = char('^')(input)?;
-        let (input, rank) = parse1(input)?;
-        let (input, _) = char('^')(input)?;
-        let (input, lang) = parse(input)?;
-        let (input, _) = char('^')(input)?;
-        let (input, value_2) = parse2(input)?;
-        let (input, _) = char('^')(input)?;
-        let (input, value_3) = parse2(input)?;
-        Ok((
-            input,
-            Self {
-                id_username,
-                value_1,
-                rank: rank.1,
-                lang,
-                value_2: value_2.1,
-                value_3: value_3.1,
-            },
-        ))
+impl std::ops::BitOr for UserFlags {
+    type Output = UserFlags;
+
+    fn bitor(self, rhs: UserFlags) -> UserFlags {
+        UserFlags(self.0 | rhs.0)
+    }
+}
+
+impl Parse for UserFlags {
+    fn parse(input: &str) -> IResult<&str, Self> {
+        let (input, letters) = take_while(|c| UserFlags::from_letter(c).is_some())(input)?;
+        let flags = letters
+            .chars()
+            .filter_map(UserFlags::from_letter)
+            .fold(UserFlags::empty(), |flags, flag| flags | flag);
+        Ok((input, flags))
     }
 
     fn as_string(&self) -> String {
-        format!(
-            "{}^{}^{}^{}^{}^{}",
-            self.id_username,
-            self.value_1,
-            self.rank,
-            self.lang,
-            self.value_2.as_string(),
-            self.value_3.as_string(),
-        )
+        UserFlags::LETTERS
+            .iter()
+            .filter(|(_, flag)| self.contains(*flag))
+            .map(|(c, _)| c)
+            .collect()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[parse(notag = true, notab = true, sep = '^')]
+pub struct User {
+    pub id_username: String,
+    pub flags: UserFlags,
+    pub rank: i32,
+    pub lang: String,
+    pub value_2: NonEmptyOption<String>,
+    pub value_3: NonEmptyOption<String>,
+}
+
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum JoinLeaveReason {
+    #[parse(tag = "1")]
     StartedSP,
+    #[parse(tag = "2")]
     CreatedMP(String),
+    #[parse(tag = "3")]
     JoinedMP(String),
+    #[parse(tag = "4")]
     LeftLobby,
+    #[parse(tag = "5")]
     LostConnection,
 }
 
-#[derive(Debug)]
-pub struct PlayerInfo(Vec<bool>);
+/// A player slot of a game in `game\tendstroke`: `t`, `f` or `p`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlayerState {
+    InHole,
+    NotInHole,
+    /// The player has left the game.
+    Left,
+}
+
+impl PlayerState {
+    pub fn is_in_hole(self) -> bool {
+        self == PlayerState::InHole
+    }
+
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            't' => Some(PlayerState::InHole),
+            'f' => Some(PlayerState::NotInHole),
+            'p' => Some(PlayerState::Left),
+            _ => None,
+        }
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            PlayerState::InHole => 't',
+            PlayerState::NotInHole => 'f',
+            PlayerState::Left => 'p',
+        }
+    }
+}
+
+impl From<bool> for PlayerState {
+    /// `t` or `f`, whether the ball is in the hole.
+    fn from(in_hole: bool) -> Self {
+        match in_hole {
+            true => PlayerState::InHole,
+            false => PlayerState::NotInHole,
+        }
+    }
+}
+
+/// One char for every player slot of a game, `tfp`.
+///
+/// In `game\tendstroke` it is the [`PlayerState`] of every player after the stroke.
+/// `game\tstarttrack` only uses `t` for a slot with a player and `f` for an empty one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct PlayerInfo(pub Vec<PlayerState>);
+
+impl PlayerInfo {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<PlayerState> {
+        self.0.get(index).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = PlayerState> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl FromIterator<PlayerState> for PlayerInfo {
+    fn from_iter<I: IntoIterator<Item = PlayerState>>(iter: I) -> Self {
+        PlayerInfo(iter.into_iter().collect())
+    }
+}
 
 impl Parse for PlayerInfo {
     fn parse(input: &str) -> IResult<&str, Self>
     where
         Self: Sized,
     {
-        let mut ret = Vec::new();
         let (input, s) = String::parse(input)?;
-        for i in s.chars() {
-            match i {
-                'f' => ret.push(true),
-                't' => ret.push(false),
-                _ => return Err(nom::Err::Error(make_error(input, ErrorKind::Fail))),
-            }
+        if s.is_empty() {
+            return Err(nom::Err::Error(make_error(input, ErrorKind::Fail)));
+        }
+        match s.chars().map(PlayerState::from_char).collect() {
+            Some(states) => Ok((input, PlayerInfo(states))),
+            None => Err(nom::Err::Error(make_error(input, ErrorKind::Fail))),
         }
-        Ok((input, PlayerInfo(ret)))
     }
 
     fn as_string(&self) -> String {
-        let mut ret = String::new();
-        for i in &self.0 {
-            if *i == true {
-                ret.push('t');
-            } else {
-                ret.push('f');
-            }
-        }
-        ret
+        self.iter().map(PlayerState::as_char).collect()
     }
 }
 
-impl Parse for JoinLeaveReason {
-    fn parse(input: &str) -> IResult<&str, Self> {
-        let (input, s) = i32::parse(input)?;
-        match s {
-            1 => Ok((input, JoinLeaveReason::StartedSP)),
-            2 => {
-                let (input, _) = char('\t')(input)?;
-                let (input, s) = String::parse(input)?;
-                Ok((input, JoinLeaveReason::CreatedMP(s)))
+/// Sent instead of the players in `game\tstarttrack` when a track is played in track
+/// test mode (`logintype\tttm`), `ttm1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct TrackTestMode(pub u8);
+
+impl Parse for TrackTestMode {
+    fn parse(input: &str) -> IResult<&str, Self>
+    where
+        Self: Sized,
+    {
+        let (input, _) = tag("ttm")(input)?;
+        let (input, mode) = u8::parse(input)?;
+        Ok((input, TrackTestMode(mode)))
+    }
+
+    fn as_string(&self) -> String {
+        format!("ttm{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arbitrary::assert_roundtrip;
+    use crate::common::{
+        JoinLeaveReason, NonEmptyOption, Packet, PacketNumber, Parse, PlayerInfo, User, UserFlags,
+    };
+    use nom::IResult;
+    use parsemacro::Parse as ParseD;
+
+    #[derive(Debug, PartialEq, ParseD)]
+    #[parse(tag = "test")]
+    struct Fields {
+        packet_number: PacketNumber,
+        name: String,
+        #[parse(sep = ':')]
+        port: u32,
+        #[parse(skip)]
+        cached: Vec<u8>,
+        #[parse(skip, default = "default_count")]
+        count: usize,
+        #[parse(with = "flags")]
+        flags: Vec<bool>,
+        #[parse(rest)]
+        rest: Vec<String>,
+    }
+
+    #[derive(Debug, PartialEq, ParseD)]
+    #[parse(tag = "addr")]
+    struct Address {
+        host: String,
+        #[parse(sep = ':')]
+        port: u32,
+        #[parse(sep = '/')]
+        path: String,
+        note: String,
+    }
+
+    fn default_count() -> usize {
+        3
+    }
+
+    mod flags {
+        use nom::{bytes::complete::take_while1, combinator::map, IResult};
+
+        pub fn parse(input: &str) -> IResult<&str, Vec<bool>> {
+            map(take_while1(|c| c == 't' || c == 'f'), |s: &str| {
+                s.chars().map(|c| c == 't').collect()
+            })(input)
+        }
+
+        pub fn as_string(flags: &[bool]) -> String {
+            flags.iter().map(|&f| if f { 't' } else { 'f' }).collect()
+        }
+    }
+
+    #[derive(Debug, PartialEq, ParseD)]
+    #[parse(notag = true, notab = true, sep = ',')]
+    struct Point(i32, i32);
+
+    #[derive(Debug, PartialEq, ParseD)]
+    enum Shape {
+        #[parse(tag = "circle")]
+        Circle { radius: u32 },
+        #[parse(tag = "line")]
+        Line(String, String),
+        #[parse(tag = "none")]
+        Nothing,
+    }
+
+    #[test]
+    fn field_attributes() {
+        let input = "d 4 test\tlocalhost:4242\ttft\ta\tb\n";
+        let (rest, fields) = Fields::parse(input).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(
+            fields,
+            Fields {
+                packet_number: PacketNumber(4),
+                name: "localhost".into(),
+                port: 4242,
+                cached: Vec::new(),
+                count: 3,
+                flags: vec![true, false, true],
+                rest: vec!["a".into(), "b".into()],
             }
-            3 => {
-                let (input, _) = char('\t')(input)?;
-                let (input, s) = String::parse(input)?;
+        );
+        assert_eq!(fields.packet_number(), Some(PacketNumber(4)));
+        assert_eq!(fields.as_string(), input);
+    }
 
-                Ok((input, JoinLeaveReason::JoinedMP(s)))
+    #[test]
+    fn separators_per_field() {
+        // The path may contain the port's separator, only its own and the next one end it
+        let input = "addr\thost:80/a:b\tx\n";
+        let (rest, address) = Address::parse(input).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(
+            address,
+            Address {
+                host: "host".into(),
+                port: 80,
+                path: "a:b".into(),
+                note: "x".into(),
             }
-            4 => Ok((input, JoinLeaveReason::LeftLobby)),
-            5 => Ok((input, JoinLeaveReason::LostConnection)),
-            _ => Err(nom::Err::Error(make_error(input, ErrorKind::Fail))),
+        );
+        assert_eq!(address.as_string(), input);
+    }
+
+    #[test]
+    fn tuple_struct_and_enum() {
+        assert_eq!(Point::parse("-1,5\t").unwrap(), ("\t", Point(-1, 5)));
+        assert_eq!(Point(7, 8).as_string(), "7,8");
+
+        for (input, shape) in [
+            ("circle\t5", Shape::Circle { radius: 5 }),
+            ("line\ta\tb", Shape::Line("a".into(), "b".into())),
+            ("none", Shape::Nothing),
+        ] {
+            assert_eq!(Shape::parse(input).unwrap().1, shape);
+            assert_eq!(shape.as_string(), input);
+            assert_eq!(shape.packet_number(), None);
         }
+        assert!(Shape::parse("square").is_err());
     }
-    fn as_string(&self) -> String {
-        match self {
-            JoinLeaveReason::StartedSP => "1".to_string(),
-            JoinLeaveReason::CreatedMP(i) => format!("2\t{}", i),
-            JoinLeaveReason::JoinedMP(i) => format!("3\t{}", i),
-            JoinLeaveReason::LeftLobby => "4".to_string(),
-            JoinLeaveReason::LostConnection => "5".to_string(),
+
+    #[test]
+    fn join_leave_reason() {
+        for input in ["1", "2\t#1583093", "3\tgame", "4", "5"] {
+            let (rest, reason) = JoinLeaveReason::parse(input).unwrap();
+            assert_eq!(rest, "");
+            assert_eq!(reason.as_string(), input);
         }
+        assert!(matches!(
+            JoinLeaveReason::parse("2\tname").unwrap().1,
+            JoinLeaveReason::CreatedMP(name) if name == "name"
+        ));
     }
-}
 
-mod tests {
-    use crate::common::{Parse, User};
+    #[test]
+    fn player_info() {
+        use super::PlayerState::{InHole, Left, NotInHole};
+        let info = PlayerInfo::parse("tfp").unwrap().1;
+        assert_eq!(info.0, [InHole, NotInHole, Left]);
+        assert_eq!(info.get(0), Some(InHole));
+        assert_eq!(info.get(3), None);
+        assert_eq!(info.as_string(), "tfp");
+        for input in ["", "tqf", "TF", "ttm1"] {
+            assert!(PlayerInfo::parse(input).is_err(), "{:?}", input);
+        }
+        assert_roundtrip::<PlayerInfo>(100);
+        assert_roundtrip::<User>(100);
+        assert_roundtrip::<JoinLeaveReason>(100);
+    }
+
+    #[test]
+    fn user_fields() {
+        let user = User::parse("3:~anonym-2893^wn^-1^de_DE^-^clan").unwrap().1;
+        assert_eq!(user.id_username, "3:~anonym-2893");
+        assert_eq!(user.rank, -1);
+        assert_eq!(user.value_2, NonEmptyOption(None));
+        assert_eq!(user.value_3, NonEmptyOption(Some("clan".to_string())));
+    }
 
     #[test]
     fn user_parse() {
         let input = "3:~anonym-2893^wn^-1^de_DE^-^-";
-        assert_eq!(
-            User::parse(input).unwrap().1.as_string(),
-            format!("\t{}", input)
-        );
+        let user = User::parse(input).unwrap().1;
+        assert_eq!(user.flags, UserFlags::WORM | UserFlags::NO_CHALLENGES);
+        assert_eq!(user.as_string(), input);
+
+        let user = User::parse("3:Jomppppa^rvs^146^fi_FI^-^-").unwrap().1;
+        assert!(user.flags.contains(UserFlags::VIP | UserFlags::SHERIFF));
+        assert!(!user.flags.contains(UserFlags::NO_CHALLENGES));
+        assert!(User::parse("3:Jomppppa^rx^146^fi_FI^-^-").is_err());
     }
 
     #[test]
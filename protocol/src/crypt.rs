@@ -1,7 +1,10 @@
 use rand::Rng;
+
+use crate::random::JavaRandom;
 use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
 
-static CIPHER_CMDS: [&str; 68] = [
+/// Game cipher commands of protocol version 35.
+pub(crate) static CIPHER_CMDS: [&str; 68] = [
     "status\t",
     "basicinfo\t",
     "numberofusers\t",
@@ -71,15 +74,21 @@ static CIPHER_CMDS: [&str; 68] = [
     "nop\t",
     "error",
 ];
-const CIPHER_MAGIC_DEFAULT: i32 = 4;
+pub const CIPHER_MAGIC_DEFAULT: i32 = 4;
 
+#[derive(Clone)]
 pub struct GameCipher {
     cmds: Vec<String>,
 }
 
 impl GameCipher {
+    /// The cipher of the latest protocol version.
     pub fn new() -> Self {
-        let mut cmds: Vec<String> = CIPHER_CMDS.iter().map(|&s| s.to_string()).collect();
+        Self::with_commands(&CIPHER_CMDS)
+    }
+
+    pub fn with_commands(commands: &[&str]) -> Self {
+        let mut cmds: Vec<String> = commands.iter().map(|&s| s.to_string()).collect();
         cmds.sort_by(|a, b| b.len().cmp(&a.len()));
 
         Self { cmds }
@@ -87,20 +96,24 @@ impl GameCipher {
 
     pub fn encrypt(&self, input: &str) -> String {
         if let Some(unused_char) = GameCipher::find_unused_char(input) {
-            let cmds_length = self.cmds.len();
             let mut encrypted_input = input.to_string();
 
-            for i in 0..cmds_length {
-                while let Some(index) = encrypted_input.find(&self.cmds[i]) {
-                    if !GameCipher::contains_char(&encrypted_input, index, unused_char) {
-                        let replacement = format!("{}{}", unused_char, (b' ' + i as u8) as char);
-                        encrypted_input
-                            .replace_range(index..index + self.cmds[i].len(), &replacement);
+            for (i, cmd) in self.cmds.iter().enumerate() {
+                let replacement = format!("{}{}", unused_char, (b' ' + i as u8) as char);
+                let mut start = 0;
+                while let Some(found) = encrypted_input[start..].find(cmd.as_str()) {
+                    let index = start + found;
+                    // Don't replace inside an earlier replacement
+                    if GameCipher::contains_char(&encrypted_input, index, unused_char) {
+                        start = index + 1;
+                        continue;
                     }
+                    encrypted_input.replace_range(index..index + cmd.len(), &replacement);
+                    start = index + replacement.len();
                 }
             }
 
-            encrypted_input.insert_str(0, &unused_char.to_string());
+            encrypted_input.insert(0, unused_char);
             return encrypted_input;
         }
 
@@ -108,30 +121,29 @@ impl GameCipher {
     }
 
     pub fn decrypt(&self, input: &str) -> String {
-        if let Some(first_char) = input.chars().next() {
-            let mut input = input.get(1..).unwrap_or_default().to_string();
-
-            while let Some(char_index) = input.find(first_char) {
-                if let Some(cmd_index) = input.get(char_index + 1..).and_then(|s| s.chars().next())
-                {
-                    if let Some(replacement) = self.cmds.get((cmd_index as usize).wrapping_sub(32))
-                    {
-                        input.replace_range(char_index..=char_index + 1, &replacement.to_string());
-                    }
-                }
+        let mut chars = input.chars();
+        let Some(marker) = chars.next() else {
+            return String::new();
+        };
 
-                // Move past the processed substring
-                input = input[char_index + 0..].to_string();
+        let mut output = String::with_capacity(input.len());
+        while let Some(c) = chars.next() {
+            if c != marker {
+                output.push(c);
+                continue;
+            }
+            let cmd = chars
+                .next()
+                .and_then(|index| self.cmds.get((index as usize).wrapping_sub(32)));
+            if let Some(cmd) = cmd {
+                output.push_str(cmd);
             }
-
-            return input;
         }
-
-        input.to_string()
+        output
     }
 
     fn contains_char(input: &str, pos: usize, c: char) -> bool {
-        pos > 0 && input.chars().nth(pos - 1) == Some(c)
+        input[..pos].ends_with(c)
     }
     fn find_unused_char(input: &str) -> Option<char> {
         for c in 1..32 as u8 {
@@ -146,54 +158,31 @@ impl GameCipher {
     }
 }
 
+/// Random numbers of the connection cipher, `java.util.Random` with the cipher's own
+/// way of picking from a range.
 pub struct ConnRandom {
-    multiplier: u64,
-    append: u64,
-    mask: u64,
-    nextseed: u64,
+    random: JavaRandom,
 }
 
 impl ConnRandom {
-    pub fn new(seed: u64) -> ConnRandom {
-        let multiplier = 0x5DEECE66D;
-        let append = 0xB;
-        let mask = (1u64 << 48) - 1;
-        let nextseed = (seed ^ multiplier) & mask;
-
+    pub fn new(seed: i64) -> ConnRandom {
         ConnRandom {
-            multiplier,
-            append,
-            mask,
-            nextseed,
+            random: JavaRandom::new(seed),
         }
     }
 
+    /// `min..=max` from the absolute value of `nextInt()`, not `nextInt(bound)`.
     pub fn next_int_min_max(&mut self, min: i32, max: i32) -> i32 {
-        min + (self.next_int() % (max - min + 1) as i32)
+        min + (self.next_abs() % (max - min + 1))
     }
 
-    fn next_int(&mut self) -> i32 {
-        let next = self.next();
-        if next < 0 {
-            let next = -next;
-            if next < 0 {
-                return 0;
-            }
-            return next;
-        }
-        next
-    }
-
-    fn next(&mut self) -> i32 {
-        self.nextseed = self
-            .nextseed
-            .wrapping_mul(self.multiplier)
-            .wrapping_add(self.append)
-            & self.mask;
-        (self.nextseed >> 16) as i32
+    fn next_abs(&mut self) -> i32 {
+        // Math.abs leaves i32::MIN negative, the client maps it to 0
+        self.random.next_int().checked_abs().unwrap_or(0)
     }
 }
 
+#[derive(Clone, Default)]
 pub struct Ciphers {
     pub game_cipher: Option<GameCipher>,
     pub conn_cipher: Option<ConnCipher>,
@@ -220,8 +209,61 @@ impl Ciphers {
     pub fn set_game_cipher(&mut self, game_cipher: Option<GameCipher>) {
         self.game_cipher = game_cipher;
     }
+
+    pub fn is_none(&self) -> bool {
+        self.game_cipher.is_none() && self.conn_cipher.is_none()
+    }
+
+    /// Encrypts every line of `input`. The game cipher only touches the payload of
+    /// `d <number> ` lines, the connection cipher the whole line without its `\n`.
+    pub fn encrypt(&self, input: &str) -> String {
+        let mut output = String::with_capacity(input.len() * 2);
+        for line in input.split_inclusive('\n') {
+            let (line, newline) = match line.strip_suffix('\n') {
+                Some(line) => (line, "\n"),
+                None => (line, ""),
+            };
+            let mut line = line.to_string();
+            if let Some(game_cipher) = &self.game_cipher {
+                if let Some((header, payload)) = split_data_line(&line) {
+                    line = format!("{}{}", header, game_cipher.encrypt(payload));
+                }
+            }
+            if let Some(conn_cipher) = &self.conn_cipher {
+                line = conn_cipher.encrypt(&line);
+            }
+            output.push_str(&line);
+            output.push_str(newline);
+        }
+        output
+    }
+
+    /// Decrypts a single line received from the client, without the `\n`.
+    /// `None` if the line is too short to be encrypted by the connection cipher.
+    pub fn decrypt_line(&self, line: &str) -> Option<String> {
+        let mut line = match &self.conn_cipher {
+            Some(conn_cipher) => conn_cipher.decrypt(line)?,
+            None => line.to_string(),
+        };
+        if let Some(game_cipher) = &self.game_cipher {
+            if let Some((header, payload)) = split_data_line(&line) {
+                line = format!("{}{}", header, game_cipher.decrypt(payload));
+            }
+        }
+        Some(line)
+    }
 }
 
+/// Splits `d 12 lobby\tsay...` into `d 12 ` and the payload.
+fn split_data_line(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("d ")?;
+    let number_len = rest.find(' ')?;
+    Some(line.split_at(2 + number_len + 1))
+}
+
+/// Connection cipher of the whole line. Encrypting and decrypting are exact inverses;
+/// the output hasn't been compared against a line recorded from the original client.
+#[derive(Clone)]
 pub struct ConnCipher {
     magic: i32,
     seed: i32,
@@ -242,7 +284,7 @@ impl ConnCipher {
             randoms_other: [[-1; 1920]; 2],
         };
 
-        let mut random = ConnRandom::new(seed as u64);
+        let mut random = ConnRandom::new(seed as i64);
 
         let mut index = 1;
         while index <= 125 {
@@ -385,8 +427,7 @@ impl ConnCipher {
                 cur_char = self.randoms_other[0][(cur_char - 128) as usize];
             }
 
-            output.push(Self::increment(cur_char as u8 - 2) as char); //TODO figure why this is
-                                                                      //off by 2
+            output.extend(char::from_u32(cur_char as u32));
             seedling += 1;
         }
         if rand_mod == input_length as i32 + 1 {
@@ -395,9 +436,13 @@ impl ConnCipher {
         output
     }
 
-    pub fn decrypt(&self, input: &str) -> String {
+    /// `None` if `input` is shorter than the two random chars every encrypted line has.
+    pub fn decrypt(&self, input: &str) -> Option<String> {
         let input_chars: Vec<char> = input.chars().collect();
         let input_length = input_chars.len();
+        if input_length < 2 {
+            return None;
+        }
         let mut output = String::with_capacity(input_length - 2);
 
         let first_random = Self::decrement(input_chars[0] as i32);
@@ -436,25 +481,46 @@ impl ConnCipher {
                 seedling -= 2;
             }
 
-            output.push(Self::increment(cur_char as u8 - 2) as char); //TODO
+            output.extend(char::from_u32(cur_char as u32));
             seedling -= 1;
         }
 
-        output
+        Some(output)
     }
 }
 
+#[cfg(test)]
 mod tests {
 
+    use super::Ciphers;
     use super::ConnCipher;
     use super::GameCipher;
     #[test]
     fn encrypt_conn_test() {
         let conn = ConnCipher::new(4, 148153586);
         let txt = conn.encrypt("c new\n");
-        let lol = conn.decrypt(&txt);
 
-        assert_eq!("c new\n", conn.decrypt(&txt));
+        assert_eq!(conn.decrypt(&txt).unwrap(), "c new\n");
+
+        let line = "d 3 lobby\tsay\tmoi\täö€";
+        let txt = conn.encrypt(line);
+        assert!(!txt.contains(['\n', '\r']));
+        assert_eq!(conn.decrypt(&txt).unwrap(), line);
+
+        assert_eq!(conn.encrypt("").chars().count(), 2);
+        assert_eq!(conn.decrypt(&conn.encrypt("")).unwrap(), "");
+        assert_eq!(conn.decrypt(""), None);
+        assert_eq!(conn.decrypt("a"), None);
+    }
+
+    #[test]
+    fn encrypt_conn_known_answer() {
+        // Pins the output, a changed char mapping breaks every client
+        let conn = ConnCipher::new(4, 148153586);
+        let encrypted = "\u{13}m]tk\"f\u{7}A8he\u{5}'\u{18}8 e8";
+        let line = "d 1 lobby\tsay\tmoi";
+        assert_eq!(conn.encrypt_non_random(17, 99, line), encrypted);
+        assert_eq!(conn.decrypt(encrypted).unwrap(), line);
     }
 
     #[test]
@@ -469,5 +535,23 @@ mod tests {
             cipher.decrypt(&cipher.encrypt("game\tbeginstroke\t7ors\n")),
             "game\tbeginstroke\t7ors\n"
         );
+        // "Dante" must not match across the replacement for "game\t"
+        let input = "game\tante\tDante\tgame";
+        assert_eq!(cipher.decrypt(&cipher.encrypt(input)), input);
+    }
+
+    #[test]
+    fn encrypt_lines_test() {
+        let ciphers = Ciphers::new(Some(GameCipher::new()), Some(ConnCipher::new(4, 148153586)));
+        let input = "c id 1\nd 1 status\tlogin\nd 2 basicinfo\tt\t0\tt\tf\n";
+        let encrypted = ciphers.encrypt(input);
+        assert_eq!(encrypted.matches('\n').count(), 3);
+
+        let decrypted: Vec<String> = encrypted
+            .lines()
+            .map(|line| ciphers.decrypt_line(line).unwrap())
+            .collect();
+        assert_eq!(decrypted, input.lines().collect::<Vec<_>>());
+        assert_eq!(Ciphers::none().encrypt(input), input);
     }
 }
@@ -1,5 +1,15 @@
 #![feature(assert_matches)]
+#[cfg(any(test, feature = "arbitrary"))]
+pub mod arbitrary;
 pub mod client;
+pub mod codec;
 pub mod common;
 pub mod crypt;
+pub mod mirror;
+pub mod physics;
+pub mod random;
+pub mod replay;
 pub mod server;
+pub mod stroke;
+pub mod track;
+pub mod version;
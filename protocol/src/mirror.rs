@@ -0,0 +1,206 @@
+//! Following a game from what the server sends, so bots and clients can play
+//! strokes and report where the balls ended.
+
+use std::collections::HashSet;
+
+use rand::Rng;
+
+use crate::{
+    common::{Collision, PlayerInfo, PlayerState},
+    physics::{Position, Simulation},
+    server::ServerToClient,
+    stroke::{Stroke, TRACK_HEIGHT, TRACK_WIDTH},
+    track::{map::Special, Track},
+};
+
+/// The game a client is in, mirrored from what the server sends.
+pub struct GameMirror {
+    /// Own player index, from `game\towninfo`.
+    pub index: Option<usize>,
+    collisions: bool,
+    /// Player slots, including empty ones.
+    players: usize,
+    /// Slots without a player, they are reported as `p`.
+    left: HashSet<usize>,
+    simulation: Option<Simulation>,
+    holes: Vec<Position>,
+}
+
+impl Default for GameMirror {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameMirror {
+    pub fn new() -> Self {
+        Self {
+            index: None,
+            collisions: true,
+            players: 0,
+            left: HashSet::new(),
+            simulation: None,
+            holes: Vec::new(),
+        }
+    }
+
+    /// Follows `packet`. A `game\tbeginstroke` returns the player index and
+    /// stroke, for the caller to [`stroke`](GameMirror::stroke) and report.
+    pub fn handle(&mut self, packet: &ServerToClient) -> Option<(usize, Stroke)> {
+        match packet {
+            ServerToClient::GameGameInfo(info) => {
+                self.collisions = info.collision == Collision::Yes;
+                self.players = info.players;
+            }
+            ServerToClient::GameOwnInfo(own) => self.index = Some(own.index),
+            ServerToClient::GameStartTrack(start) => {
+                self.start_track(&start.track, start.seed, &start.players)
+            }
+            ServerToClient::GamePart(part) => {
+                self.left.insert(part.index);
+            }
+            ServerToClient::GameBeginStroke(stroke) => return Some((stroke.index, stroke.coords)),
+            _ => {}
+        }
+        None
+    }
+
+    /// Puts the balls on `track`. `players` has `t` for the slots with a player.
+    fn start_track(&mut self, track: &Track, seed: i32, players: &PlayerInfo) {
+        self.players = players.0.len();
+        self.left = players
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| !state.is_in_hole())
+            .map(|(index, _)| index)
+            .collect();
+        self.simulation = None;
+        self.holes.clear();
+        let map = match track.decode_map() {
+            Ok(map) => map,
+            Err(e) => {
+                log::warn!("cannot read track {:?}: {}", track.name, e);
+                return;
+            }
+        };
+        self.holes = map
+            .specials()
+            .filter(|(_, _, special)| *special == Special::Hole)
+            .map(|(x, y, _)| Position::tile_center(x, y))
+            .collect();
+        let mut simulation = Simulation::new(map, seed, self.players);
+        simulation.set_collisions(self.collisions);
+        self.simulation = Some(simulation);
+    }
+
+    /// Plays a stroke and returns who is in the hole afterwards.
+    pub fn stroke(&mut self, index: usize, stroke: Stroke) -> PlayerInfo {
+        let in_hole: Vec<bool> = match &mut self.simulation {
+            Some(simulation) if index < simulation.balls().len() => {
+                simulation.stroke(index, stroke);
+                simulation.balls().iter().map(|ball| ball.in_hole).collect()
+            }
+            _ => vec![false; self.players],
+        };
+        in_hole
+            .into_iter()
+            .enumerate()
+            .map(|(index, in_hole)| match self.left.contains(&index) {
+                true => PlayerState::Left,
+                false => in_hole.into(),
+            })
+            .collect()
+    }
+
+    /// The track as it is now, `None` if it doesn't decode.
+    pub fn simulation(&self) -> Option<&Simulation> {
+        self.simulation.as_ref()
+    }
+
+    /// Centers of the holes of the track.
+    pub fn holes(&self) -> &[Position] {
+        &self.holes
+    }
+}
+
+/// The hole closest to `from`.
+pub fn nearest_hole(holes: &[Position], from: Position) -> Option<Position> {
+    holes.iter().copied().min_by(|a, b| {
+        let a = (a.x - from.x).hypot(a.y - from.y);
+        let b = (b.x - from.x).hypot(b.y - from.y);
+        a.total_cmp(&b)
+    })
+}
+
+/// A stroke from `from` at `angle`, aiming `distance` pixels away. Farther is
+/// stronger.
+pub fn aim(from: Position, angle: f64, distance: f64) -> Stroke {
+    let x = from.x + angle.cos() * distance;
+    let y = from.y + angle.sin() * distance;
+    Stroke {
+        x: x.clamp(0.0, (TRACK_WIDTH - 1) as f64) as u16,
+        y: y.clamp(0.0, (TRACK_HEIGHT - 1) as f64) as u16,
+        mode: 0,
+    }
+}
+
+/// Shoots anywhere.
+pub fn random_stroke() -> Stroke {
+    let mut rng = rand::thread_rng();
+    Stroke {
+        x: rng.gen_range(0..TRACK_WIDTH),
+        y: rng.gen_range(0..TRACK_HEIGHT),
+        mode: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::{PacketNumber, Parse, PlayerInfo, PlayerState},
+        server::{GameStartTrack, ServerToClient},
+        track::{map::TrackMap, Track},
+    };
+
+    use super::GameMirror;
+
+    fn packet(line: &str) -> ServerToClient {
+        ServerToClient::parse(line).unwrap().1
+    }
+
+    #[test]
+    fn follows_game() {
+        use PlayerState::*;
+
+        let mut game = GameMirror::new();
+        let start = ServerToClient::GameStartTrack(GameStartTrack {
+            packet_number: PacketNumber(3),
+            players: PlayerInfo(vec![InHole, InHole, NotInHole]),
+            seed: 5,
+            track: Track {
+                name: "Empty".to_string(),
+                map: TrackMap::new().encode().unwrap(),
+                ..Default::default()
+            },
+        });
+        for packet in [
+            packet("d 1 game\tgameinfo\t-\tf\t0\t3\t1\t1\t20\t60\t0\t1\t0\t0\tf\n"),
+            packet("d 2 game\towninfo\t1\t~load-1\t-\n"),
+            start,
+        ] {
+            assert!(game.handle(&packet).is_none(), "{:?}", packet);
+        }
+        assert_eq!(game.index, Some(1));
+        assert!(game.simulation().is_some());
+
+        let stroke = game.handle(&packet("d 4 game\tbeginstroke\t0\t70q4\n"));
+        let (index, stroke) = stroke.unwrap();
+        assert_eq!(index, 0);
+        let in_hole = game.stroke(index, stroke);
+        assert_eq!(in_hole, PlayerInfo(vec![NotInHole, NotInHole, Left]));
+
+        assert!(game.handle(&packet("d 5 game\tpart\t0\t4\n")).is_none());
+        let in_hole = game.stroke(1, stroke);
+        assert_eq!(in_hole, PlayerInfo(vec![Left, NotInHole, Left]));
+    }
+}
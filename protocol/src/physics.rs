@@ -0,0 +1,638 @@
+//! An approximation of golf ball physics, so the server and bots can guess where a
+//! stroke ends without asking a client.
+//!
+//! This is not a port of the client. The rules below are modelled after how the
+//! client plays, but the constants are estimates and no result has been compared
+//! with what the client computes for the same stroke. Whatever the clients report
+//! stays authoritative; disagreements are only worth recording.
+//!
+//! A [`Simulation`] holds the balls of one track. Every stroke is run frame by frame
+//! until all balls have stopped. A frame moves the moving balls in [`SUBSTEPS`]
+//! steps, bouncing them off walls and each other. After that the ground under each
+//! ball applies: friction, slopes, liquids, the hole, teleports, mines and magnets.
+//! Anything random, the shake of strong strokes, teleport exits and mines, is
+//! drawn from a [`JavaRandom`] seeded with the `seed` of `game\tstarttrack`. The
+//! same strokes in the same order always give the same result.
+//!
+//! The client collides against the pixel masks of tile shapes, which this crate
+//! doesn't have. Here a shaped tile is all foreground if the foreground is a wall,
+//! and all background otherwise. Movable and breakable blocks are plain walls.
+//!
+//! A port still needs the client's constants, the pixel masks of the tile shapes
+//! and its friction, slope and magnet rules, checked against strokes recorded from
+//! the client. Until then nothing should take these results as the game's.
+
+use std::f64::consts::{FRAC_1_SQRT_2, TAU};
+
+use crate::random::JavaRandom;
+use crate::stroke::{Stroke, TRACK_HEIGHT, TRACK_WIDTH};
+use crate::track::map::{Color, Element, Special, Tile, TrackMap, TILE_SIZE};
+use crate::track::Track;
+
+pub const BALL_RADIUS: f64 = 6.0;
+/// Steps a frame is split into, so fast balls can't skip over walls.
+pub const SUBSTEPS: u32 = 10;
+/// A stroke that is still moving after this many frames stops where it is. It can
+/// only happen on slopes, which keep balls rolling forever.
+pub const MAX_FRAMES: u32 = 10_000;
+
+const MIN_POWER: f64 = 0.075;
+const MAX_POWER: f64 = 6.5;
+const STOP_SPEED: f64 = 0.075;
+/// Fastest a ball can roll over the hole and still fall in.
+const HOLE_SPEED: f64 = 4.0;
+const DOWNHILL_FORCE: f64 = 0.025;
+const MAGNET_RANGE: f64 = 4.0 * TILE_SIZE as f64;
+const MAGNET_FORCE: f64 = 0.05;
+const MINE_SPEED: f64 = 2.6;
+const BIG_MINE_SPEED: f64 = 5.2;
+
+/// A point on the track in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Position {
+    pub fn new(x: f64, y: f64) -> Self {
+        Position { x, y }
+    }
+
+    /// Center of the tile at `x`, `y`.
+    pub fn tile_center(x: usize, y: usize) -> Self {
+        let half = TILE_SIZE as f64 / 2.0;
+        Position::new((x * TILE_SIZE) as f64 + half, (y * TILE_SIZE) as f64 + half)
+    }
+
+    fn distance(&self, other: Position) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+/// Where player `index` starts: their colored start if the track has one, else
+/// the common start, else the middle of the track.
+pub fn start_position(map: &TrackMap, index: usize) -> Position {
+    let color = Color::ALL.get(index).copied();
+    let find = |wanted: Special| {
+        map.specials()
+            .find(|&(_, _, special)| special == wanted)
+            .map(|(x, y, _)| Position::tile_center(x, y))
+    };
+    color
+        .and_then(|color| find(Special::PlayerStart(color)))
+        .or_else(|| find(Special::Start))
+        .unwrap_or(Position::new(
+            TRACK_WIDTH as f64 / 2.0,
+            TRACK_HEIGHT as f64 / 2.0,
+        ))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ball {
+    pub position: Position,
+    pub speed_x: f64,
+    pub speed_y: f64,
+    pub in_hole: bool,
+}
+
+impl Ball {
+    fn new(position: Position) -> Self {
+        Ball {
+            position,
+            speed_x: 0.0,
+            speed_y: 0.0,
+            in_hole: false,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed_x.hypot(self.speed_y)
+    }
+
+    pub fn is_moving(&self) -> bool {
+        !self.in_hole && (self.speed_x != 0.0 || self.speed_y != 0.0)
+    }
+
+    fn stop(&mut self) {
+        self.speed_x = 0.0;
+        self.speed_y = 0.0;
+    }
+}
+
+/// Where the struck ball ended up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrokeOutcome {
+    pub position: Position,
+    pub in_hole: bool,
+    /// Frames until every ball stopped.
+    pub frames: u32,
+}
+
+/// The balls on one track.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    map: TrackMap,
+    random: JavaRandom,
+    balls: Vec<Ball>,
+    starts: Vec<Position>,
+    /// Where each ball was struck from, water puts it back there.
+    stroke_starts: Vec<Position>,
+    magnets: Vec<(Position, bool)>,
+    collisions: bool,
+}
+
+impl Simulation {
+    /// Places `players` balls on their start positions.
+    pub fn new(map: TrackMap, seed: i32, players: usize) -> Self {
+        let starts: Vec<_> = (0..players).map(|i| start_position(&map, i)).collect();
+        let magnets = map
+            .specials()
+            .filter_map(|(x, y, special)| match special {
+                Special::MagnetAttract => Some((Position::tile_center(x, y), true)),
+                Special::MagnetRepel => Some((Position::tile_center(x, y), false)),
+                _ => None,
+            })
+            .collect();
+        Simulation {
+            map,
+            random: JavaRandom::new(seed as i64),
+            balls: starts.iter().map(|&start| Ball::new(start)).collect(),
+            stroke_starts: starts.clone(),
+            starts,
+            magnets,
+            collisions: true,
+        }
+    }
+
+    pub fn from_track(track: &Track, seed: i32, players: usize) -> anyhow::Result<Self> {
+        Ok(Simulation::new(track.decode_map()?, seed, players))
+    }
+
+    /// Whether balls bounce off each other, the `Collision` setting of a game.
+    pub fn set_collisions(&mut self, collisions: bool) {
+        self.collisions = collisions;
+    }
+
+    pub fn balls(&self) -> &[Ball] {
+        &self.balls
+    }
+
+    pub fn ball(&self, index: usize) -> &Ball {
+        &self.balls[index]
+    }
+
+    pub fn set_position(&mut self, index: usize, position: Position) {
+        self.balls[index] = Ball::new(position);
+    }
+
+    /// Strikes ball `index` towards `stroke` and runs until every ball stopped.
+    pub fn stroke(&mut self, index: usize, stroke: Stroke) -> StrokeOutcome {
+        if !self.balls[index].in_hole {
+            self.stroke_starts[index] = self.balls[index].position;
+            let (speed_x, speed_y) = self.stroke_speed(index, stroke);
+            let ball = &mut self.balls[index];
+            ball.speed_x = speed_x;
+            ball.speed_y = speed_y;
+        }
+
+        let mut frames = 0;
+        while self.balls.iter().any(Ball::is_moving) {
+            if frames == MAX_FRAMES {
+                self.balls.iter_mut().for_each(Ball::stop);
+                break;
+            }
+            self.frame();
+            frames += 1;
+        }
+
+        let ball = &self.balls[index];
+        StrokeOutcome {
+            position: ball.position,
+            in_hole: ball.in_hole,
+            frames,
+        }
+    }
+
+    fn stroke_speed(&mut self, index: usize, stroke: Stroke) -> (f64, f64) {
+        let position = self.balls[index].position;
+        let dx = stroke.x as f64 - position.x;
+        let dy = stroke.y as f64 - position.y;
+        let distance = dx.hypot(dy);
+        if distance == 0.0 {
+            return (0.0, 0.0);
+        }
+        let power = ((distance - 5.0) / 30.0).clamp(MIN_POWER, MAX_POWER);
+        let (x, y) = (dx / distance * power, dy / distance * power);
+        let (x, y) = match stroke.mode {
+            1 => (-x, -y),
+            2 => (-y, x),
+            3 => (y, -x),
+            _ => (x, y),
+        };
+        // Strong strokes are a little inaccurate
+        let shake = (power / MAX_POWER).powi(2) * 0.5;
+        let shake_x = shake * (self.random.next_double() - 0.5);
+        let shake_y = shake * (self.random.next_double() - 0.5);
+        (x + shake_x, y + shake_y)
+    }
+
+    fn frame(&mut self) {
+        for index in 0..self.balls.len() {
+            if !self.balls[index].is_moving() {
+                continue;
+            }
+            for _ in 0..SUBSTEPS {
+                self.step(index);
+            }
+            self.apply_ground(index);
+        }
+    }
+
+    fn step(&mut self, index: usize) {
+        let ball = self.balls[index];
+        let step_x = ball.speed_x / SUBSTEPS as f64;
+        let step_y = ball.speed_y / SUBSTEPS as f64;
+        let mut next = Position::new(ball.position.x + step_x, ball.position.y + step_y);
+        let mut speed_x = ball.speed_x;
+        let mut speed_y = ball.speed_y;
+
+        let wall_x = self.wall_at(next.x + BALL_RADIUS * step_x.signum(), ball.position.y);
+        let wall_y = self.wall_at(ball.position.x, next.y + BALL_RADIUS * step_y.signum());
+        let wall_corner = match (wall_x, wall_y) {
+            (None, None) => self.wall_at(
+                next.x + BALL_RADIUS * FRAC_1_SQRT_2 * step_x.signum(),
+                next.y + BALL_RADIUS * FRAC_1_SQRT_2 * step_y.signum(),
+            ),
+            _ => None,
+        };
+        if let Some(bounce) = wall_x.or(wall_corner) {
+            speed_x = -speed_x * bounce;
+            next.x = ball.position.x;
+        }
+        if let Some(bounce) = wall_y.or(wall_corner) {
+            speed_y = -speed_y * bounce;
+            next.y = ball.position.y;
+        }
+
+        if self.collisions {
+            for other in 0..self.balls.len() {
+                let target = self.balls[other];
+                if other == index || target.in_hole {
+                    continue;
+                }
+                let distance = next.distance(target.position);
+                if distance == 0.0 || distance >= 2.0 * BALL_RADIUS {
+                    continue;
+                }
+                let normal_x = (target.position.x - next.x) / distance;
+                let normal_y = (target.position.y - next.y) / distance;
+                let approach =
+                    (speed_x - target.speed_x) * normal_x + (speed_y - target.speed_y) * normal_y;
+                if approach <= 0.0 {
+                    continue;
+                }
+                // Equal masses swap the speed along the line between the balls
+                speed_x -= approach * normal_x;
+                speed_y -= approach * normal_y;
+                let target = &mut self.balls[other];
+                target.speed_x += approach * normal_x;
+                target.speed_y += approach * normal_y;
+            }
+        }
+
+        let ball = &mut self.balls[index];
+        ball.position = next;
+        ball.speed_x = speed_x;
+        ball.speed_y = speed_y;
+    }
+
+    fn apply_ground(&mut self, index: usize) {
+        let position = self.balls[index].position;
+        let Some((tile_x, tile_y)) = tile_at(position.x, position.y) else {
+            return;
+        };
+        let tile = self.map.tile(tile_x, tile_y);
+        let element = element(&tile);
+
+        match element {
+            Element::Water => return self.reset(index, self.stroke_starts[index]),
+            Element::Acid => return self.reset(index, self.starts[index]),
+            Element::Downhill { direction } => {
+                let (x, y) = downhill_direction(direction);
+                let ball = &mut self.balls[index];
+                ball.speed_x += DOWNHILL_FORCE * x;
+                ball.speed_y += DOWNHILL_FORCE * y;
+            }
+            _ => {}
+        }
+
+        match tile.special() {
+            Some(Special::Hole) if self.balls[index].speed() < HOLE_SPEED => {
+                let ball = &mut self.balls[index];
+                ball.in_hole = true;
+                ball.position = Position::tile_center(tile_x, tile_y);
+                ball.stop();
+                return;
+            }
+            Some(Special::TeleportStart(color)) => self.teleport(index, color),
+            Some(Special::Mine {
+                big,
+                exploded: false,
+            }) => {
+                self.map.set(
+                    tile_x,
+                    tile_y,
+                    Tile::Special {
+                        special: Special::Mine {
+                            big,
+                            exploded: true,
+                        },
+                        background: element.into(),
+                    },
+                );
+                let angle = self.random.next_double() * TAU;
+                let speed = if big { BIG_MINE_SPEED } else { MINE_SPEED };
+                let ball = &mut self.balls[index];
+                ball.speed_x = angle.cos() * speed;
+                ball.speed_y = angle.sin() * speed;
+            }
+            _ => {}
+        }
+
+        let ball = &mut self.balls[index];
+        for &(magnet, attract) in &self.magnets {
+            let distance = ball.position.distance(magnet);
+            if distance == 0.0 || distance >= MAGNET_RANGE {
+                continue;
+            }
+            let force = MAGNET_FORCE * (1.0 - distance / MAGNET_RANGE);
+            let force = if attract { force } else { -force };
+            ball.speed_x += force * (magnet.x - ball.position.x) / distance;
+            ball.speed_y += force * (magnet.y - ball.position.y) / distance;
+        }
+
+        let friction = friction(element);
+        ball.speed_x *= friction;
+        ball.speed_y *= friction;
+        let on_slope = matches!(element, Element::Downhill { .. });
+        if ball.speed() >= STOP_SPEED || on_slope {
+            return;
+        }
+        ball.stop();
+        match element {
+            Element::WaterSwamp => self.reset(index, self.stroke_starts[index]),
+            Element::AcidSwamp => self.reset(index, self.starts[index]),
+            _ => {}
+        }
+    }
+
+    fn teleport(&mut self, index: usize, color: Color) {
+        let exits: Vec<_> = self
+            .map
+            .specials()
+            .filter(|&(_, _, special)| special == Special::TeleportExit(color))
+            .map(|(x, y, _)| Position::tile_center(x, y))
+            .collect();
+        if exits.is_empty() {
+            return;
+        }
+        let exit = exits[self.random.next_int_bounded(exits.len() as i32) as usize];
+        self.balls[index].position = exit;
+    }
+
+    fn reset(&mut self, index: usize, position: Position) {
+        self.balls[index] = Ball::new(position);
+    }
+
+    /// How much of its speed a ball keeps when bouncing off a wall at `x`, `y`,
+    /// `None` if there is no wall.
+    fn wall_at(&self, x: f64, y: f64) -> Option<f64> {
+        let element = match tile_at(x, y) {
+            Some((x, y)) => element(&self.map.tile(x, y)),
+            // The edge of the track is a wall
+            None => Element::Block,
+        };
+        match element {
+            Element::Block => Some(0.81),
+            Element::StickyBlock => Some(0.05),
+            Element::BouncyBlock => Some(1.0),
+            _ => None,
+        }
+    }
+}
+
+/// Runs a single stroke of a lone ball at `start` on `track`.
+pub fn simulate_stroke(
+    track: &Track,
+    start: Position,
+    stroke: Stroke,
+    seed: i32,
+) -> anyhow::Result<StrokeOutcome> {
+    let mut simulation = Simulation::from_track(track, seed, 1)?;
+    simulation.set_position(0, start);
+    Ok(simulation.stroke(0, stroke))
+}
+
+fn tile_at(x: f64, y: f64) -> Option<(usize, usize)> {
+    if x < 0.0 || y < 0.0 || x >= TRACK_WIDTH as f64 || y >= TRACK_HEIGHT as f64 {
+        return None;
+    }
+    Some((x as usize / TILE_SIZE, y as usize / TILE_SIZE))
+}
+
+/// What the ball touches on `tile`, see the module docs for shaped tiles.
+fn element(tile: &Tile) -> Element {
+    match *tile {
+        Tile::Empty { foreground, .. } => Element::from(foreground),
+        Tile::Normal {
+            shape: 0,
+            foreground,
+            ..
+        } => Element::from(foreground),
+        Tile::Normal {
+            foreground,
+            background,
+            ..
+        } => {
+            let foreground = Element::from(foreground);
+            if foreground.is_wall() {
+                foreground
+            } else {
+                Element::from(background)
+            }
+        }
+        Tile::Special {
+            special: Special::MovableBlock | Special::BreakableBlock { .. },
+            ..
+        } => Element::Block,
+        Tile::Special { background, .. } => Element::from(background),
+    }
+}
+
+/// Share of its speed a ball keeps every frame.
+fn friction(element: Element) -> f64 {
+    match element {
+        Element::Dirt => 0.92,
+        Element::Mud => 0.8,
+        Element::Ice => 0.9975,
+        Element::WaterSwamp | Element::AcidSwamp => 0.95,
+        Element::Water | Element::Acid => 0.0,
+        _ => 0.9935,
+    }
+}
+
+/// Unit vector of a slope, 0 is north and steps are 45 degrees clockwise.
+fn downhill_direction(direction: u8) -> (f64, f64) {
+    let d = FRAC_1_SQRT_2;
+    match direction % 8 {
+        0 => (0.0, -1.0),
+        1 => (d, -d),
+        2 => (1.0, 0.0),
+        3 => (d, d),
+        4 => (0.0, 1.0),
+        5 => (-d, d),
+        6 => (-1.0, 0.0),
+        _ => (-d, -d),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simulate_stroke, start_position, Position, Simulation, StrokeOutcome};
+    use crate::stroke::Stroke;
+    use crate::track::map::{Color, Element, Special, Tile, TrackMap};
+    use crate::track::Track;
+
+    fn ground(element: Element) -> Tile {
+        Tile::Normal {
+            shape: 0,
+            foreground: element.into(),
+            background: element.into(),
+        }
+    }
+
+    fn special(special: Special) -> Tile {
+        Tile::Special {
+            special,
+            background: Element::Grass.into(),
+        }
+    }
+
+    /// An empty grass track with the ball in tile 5,12.
+    fn simulation(map: TrackMap) -> Simulation {
+        let mut simulation = Simulation::new(map, 1234, 1);
+        simulation.set_position(0, Position::tile_center(5, 12));
+        simulation
+    }
+
+    fn stroke(x: u16, y: u16) -> Stroke {
+        Stroke::new(x, y, 0).unwrap()
+    }
+
+    #[test]
+    fn rolls_and_stops() {
+        let mut simulation = simulation(TrackMap::new());
+        let outcome = simulation.stroke(0, stroke(200, 187));
+        assert!(!outcome.in_hole);
+        assert!(outcome.position.x > 100.0 && outcome.position.x < 700.0);
+        assert!((outcome.position.y - 187.5).abs() < 10.0);
+        assert!(!simulation.ball(0).is_moving());
+
+        let mut again = self::simulation(TrackMap::new());
+        assert_eq!(again.stroke(0, stroke(200, 187)), outcome);
+
+        let mut reversed = self::simulation(TrackMap::new());
+        reversed.set_position(0, Position::tile_center(30, 12));
+        let outcome = reversed.stroke(0, Stroke::new(500, 187, 1).unwrap());
+        assert!(outcome.position.x < 457.5);
+    }
+
+    #[test]
+    fn walls_and_water() {
+        let mut map = TrackMap::new();
+        for y in 0..25 {
+            map.set(10, y, ground(Element::Block));
+        }
+        let mut simulation = simulation(map.clone());
+        let outcome = simulation.stroke(0, stroke(400, 187));
+        assert!(outcome.position.x < 150.0);
+
+        for y in 0..25 {
+            map.set(10, y, ground(Element::Water));
+        }
+        let mut simulation = self::simulation(map);
+        let outcome = simulation.stroke(0, stroke(400, 187));
+        assert_eq!(outcome.position, Position::tile_center(5, 12));
+    }
+
+    #[test]
+    fn hole_and_teleport() {
+        let mut map = TrackMap::new();
+        map.set(8, 12, special(Special::Hole));
+        let mut simulation = simulation(map.clone());
+        let outcome = simulation.stroke(0, stroke(160, 187));
+        assert!(outcome.in_hole);
+        assert_eq!(outcome.position, Position::tile_center(8, 12));
+
+        map.set(8, 12, special(Special::TeleportStart(Color::Red)));
+        map.set(40, 3, special(Special::TeleportExit(Color::Red)));
+        let mut simulation = self::simulation(map);
+        let outcome = simulation.stroke(0, stroke(160, 187));
+        assert!(outcome.position.x > 500.0 && outcome.position.y < 150.0);
+    }
+
+    #[test]
+    fn balls_collide() {
+        let mut simulation = Simulation::new(TrackMap::new(), 1, 2);
+        simulation.set_position(0, Position::tile_center(5, 12));
+        simulation.set_position(1, Position::tile_center(8, 12));
+        simulation.stroke(0, stroke(200, 187));
+        assert!(simulation.ball(1).position.x > simulation.ball(0).position.x);
+        assert!(simulation.ball(1).position.x > Position::tile_center(8, 12).x);
+
+        simulation.set_position(0, Position::tile_center(5, 12));
+        simulation.set_position(1, Position::tile_center(8, 12));
+        simulation.set_collisions(false);
+        simulation.stroke(0, stroke(200, 187));
+        assert_eq!(simulation.ball(1).position, Position::tile_center(8, 12));
+    }
+
+    /// Pins where strokes end today, so changes to the model show up here. These
+    /// are the simulation's own results, not answers taken from the client.
+    #[test]
+    fn pinned_outcomes() {
+        fn check(outcome: StrokeOutcome, x: f64, y: f64, frames: u32) {
+            assert!(!outcome.in_hole);
+            assert!((outcome.position.x - x).abs() < 1e-6, "{:?}", outcome);
+            assert!((outcome.position.y - y).abs() < 1e-6, "{:?}", outcome);
+            assert_eq!(outcome.frames, frames);
+        }
+
+        let mut simulation = simulation(TrackMap::new());
+        let outcome = simulation.stroke(0, stroke(200, 187));
+        check(outcome, 651.6464549, 196.4206636, 601);
+
+        let track: Track = crate::track::tests::TEST_TRACK.parse().unwrap();
+        let start = start_position(&track.decode_map().unwrap(), 0);
+        assert_eq!(start, Position::tile_center(1, 1));
+        let outcome = simulate_stroke(&track, start, stroke(300, 200), 12345).unwrap();
+        check(outcome, 52.9421891, 244.1010747, 271);
+        let sideways = Stroke::new(700, 20, 2).unwrap();
+        let outcome = simulate_stroke(&track, start, sideways, 12345).unwrap();
+        check(outcome, 31.8800024, 80.0840089, 153);
+    }
+
+    #[test]
+    fn simulate_test_track() {
+        let track: Track = crate::track::tests::TEST_TRACK.parse().unwrap();
+        let start = start_position(&track.decode_map().unwrap(), 0);
+        let outcome = simulate_stroke(&track, start, stroke(300, 200), 12345).unwrap();
+        assert_eq!(
+            outcome,
+            simulate_stroke(&track, start, stroke(300, 200), 12345).unwrap()
+        );
+        assert!(outcome.position.x >= 0.0 && outcome.position.x < 735.0);
+        assert!(outcome.position.y >= 0.0 && outcome.position.y < 375.0);
+    }
+}
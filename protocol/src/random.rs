@@ -0,0 +1,243 @@
+const MULTIPLIER: i64 = 0x5DEECE66D;
+const ADDEND: i64 = 0xB;
+const MASK: i64 = (1 << 48) - 1;
+
+/// A port of `java.util.Random` that produces the same numbers for the same seed.
+///
+/// The client derives everything random in a game from `seed` of
+/// `game\tstarttrack`, so anything simulating it on the server must use this.
+#[derive(Debug, Clone)]
+pub struct JavaRandom {
+    seed: i64,
+    next_next_gaussian: Option<f64>,
+}
+
+impl JavaRandom {
+    pub fn new(seed: i64) -> Self {
+        JavaRandom {
+            seed: scramble(seed),
+            next_next_gaussian: None,
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: i64) {
+        self.seed = scramble(seed);
+        self.next_next_gaussian = None;
+    }
+
+    /// The next `bits` random bits, `next(int bits)`.
+    pub fn next(&mut self, bits: u32) -> i32 {
+        debug_assert!((1..=32).contains(&bits));
+        self.seed = self.seed.wrapping_mul(MULTIPLIER).wrapping_add(ADDEND) & MASK;
+        (self.seed >> (48 - bits)) as i32
+    }
+
+    /// Any `i32`, `nextInt()`.
+    pub fn next_int(&mut self) -> i32 {
+        self.next(32)
+    }
+
+    /// `0..bound`, `nextInt(int bound)`.
+    ///
+    /// # Panics
+    ///
+    /// If `bound` is not positive, Java throws `IllegalArgumentException`.
+    pub fn next_int_bounded(&mut self, bound: i32) -> i32 {
+        assert!(bound > 0, "bound must be positive, got {}", bound);
+        let mut r = self.next(31);
+        let m = bound - 1;
+        if bound & m == 0 {
+            return ((bound as i64 * r as i64) >> 31) as i32;
+        }
+        let mut u = r;
+        loop {
+            r = u % bound;
+            // Rejects the values that would make the last range incomplete
+            if u.wrapping_sub(r).wrapping_add(m) >= 0 {
+                return r;
+            }
+            u = self.next(31);
+        }
+    }
+
+    pub fn next_long(&mut self) -> i64 {
+        ((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+    }
+
+    pub fn next_boolean(&mut self) -> bool {
+        self.next(1) != 0
+    }
+
+    /// `0.0..1.0`
+    pub fn next_float(&mut self) -> f32 {
+        self.next(24) as f32 / (1 << 24) as f32
+    }
+
+    /// `0.0..1.0`
+    pub fn next_double(&mut self) -> f64 {
+        let high = (self.next(26) as i64) << 27;
+        let low = self.next(27) as i64;
+        (high + low) as f64 * (1.0 / (1i64 << 53) as f64)
+    }
+
+    /// Normally distributed with mean 0 and standard deviation 1, Java's polar method.
+    pub fn next_gaussian(&mut self) -> f64 {
+        if let Some(gaussian) = self.next_next_gaussian.take() {
+            return gaussian;
+        }
+        loop {
+            let v1 = 2.0 * self.next_double() - 1.0;
+            let v2 = 2.0 * self.next_double() - 1.0;
+            let s = v1 * v1 + v2 * v2;
+            if s < 1.0 && s != 0.0 {
+                let multiplier = (-2.0 * strict_log(s) / s).sqrt();
+                self.next_next_gaussian = Some(v2 * multiplier);
+                return v1 * multiplier;
+            }
+        }
+    }
+}
+
+fn scramble(seed: i64) -> i64 {
+    (seed ^ MULTIPLIER) & MASK
+}
+
+/// `StrictMath.log`, fdlibm's `__ieee754_log`. The platform `ln` is off by one ulp
+/// for some inputs, which is enough to change the gaussians.
+fn strict_log(x: f64) -> f64 {
+    const LN2_HI: f64 = f64::from_bits(0x3fe62e42fee00000);
+    const LN2_LO: f64 = f64::from_bits(0x3dea39ef35793c76);
+    const TWO54: f64 = f64::from_bits(0x4350000000000000);
+    const LG1: f64 = f64::from_bits(0x3fe5555555555593);
+    const LG2: f64 = f64::from_bits(0x3fd999999997fa04);
+    const LG3: f64 = f64::from_bits(0x3fd2492494229359);
+    const LG4: f64 = f64::from_bits(0x3fcc71c51d8e78af);
+    const LG5: f64 = f64::from_bits(0x3fc7466496cb03de);
+    const LG6: f64 = f64::from_bits(0x3fc39a09d078c69f);
+    const LG7: f64 = f64::from_bits(0x3fc2f112df3e5244);
+
+    let mut x = x;
+    let mut hx = (x.to_bits() >> 32) as i32;
+    let lx = x.to_bits() as u32;
+    let mut k = 0;
+    if hx < 0x00100000 {
+        if (hx & 0x7fffffff) as u32 | lx == 0 {
+            return f64::NEG_INFINITY;
+        }
+        if hx < 0 {
+            return f64::NAN;
+        }
+        // Subnormal, scale up
+        k -= 54;
+        x *= TWO54;
+        hx = (x.to_bits() >> 32) as i32;
+    }
+    if hx >= 0x7ff00000 {
+        return x + x;
+    }
+    k += (hx >> 20) - 1023;
+    hx &= 0x000fffff;
+    let i = (hx + 0x95f64) & 0x100000;
+    // Normalize x or x / 2 into [sqrt(2) / 2, sqrt(2))
+    x = f64::from_bits((((hx | (i ^ 0x3ff00000)) as u64) << 32) | (x.to_bits() & 0xffffffff));
+    k += i >> 20;
+    let f = x - 1.0;
+    let dk = k as f64;
+    if (0x000fffff & (2 + hx)) < 3 {
+        // |f| < 2^-20
+        if f == 0.0 {
+            return if k == 0 {
+                0.0
+            } else {
+                dk * LN2_HI + dk * LN2_LO
+            };
+        }
+        let r = f * f * (0.5 - 1.0 / 3.0 * f);
+        return if k == 0 {
+            f - r
+        } else {
+            dk * LN2_HI - ((r - dk * LN2_LO) - f)
+        };
+    }
+    let s = f / (2.0 + f);
+    let z = s * s;
+    let w = z * z;
+    let t1 = w * (LG2 + w * (LG4 + w * LG6));
+    let t2 = z * (LG1 + w * (LG3 + w * (LG5 + w * LG7)));
+    let r = t2 + t1;
+    if ((hx - 0x6147a) | (0x6b851 - hx)) > 0 {
+        let hfsq = 0.5 * f * f;
+        if k == 0 {
+            f - (hfsq - s * (hfsq + r))
+        } else {
+            dk * LN2_HI - ((hfsq - (s * (hfsq + r) + dk * LN2_LO)) - f)
+        }
+    } else if k == 0 {
+        f - s * (f - r)
+    } else {
+        dk * LN2_HI - ((s * (f - r) - dk * LN2_LO) - f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JavaRandom;
+
+    // Printed by java.util.Random of OpenJDK, floats and doubles as their raw bits
+    #[test]
+    fn java_vectors() {
+        let mut random = JavaRandom::new(0);
+        assert_eq!(random.next_int(), -1155484576);
+        assert_eq!(random.next_int(), -723955400);
+        assert_eq!(random.next_int_bounded(10), 9);
+        assert_eq!(random.next_int_bounded(16), 9);
+        assert_eq!(random.next_int_bounded(1073741825), 663681053);
+        assert_eq!(random.next_int_bounded(i32::MAX), 1182054491);
+        assert_eq!(random.next_long(), 2158390814503909950);
+        assert!(random.next_boolean());
+        assert!(!random.next_boolean());
+        assert_eq!(random.next_float().to_bits(), 1048669152);
+        assert_eq!(random.next_double().to_bits(), 4600610571393367700);
+        assert_eq!(random.next_gaussian().to_bits() as i64, 4595389773134917419);
+        assert_eq!(
+            random.next_gaussian().to_bits() as i64,
+            -4632361175826196606
+        );
+        assert_eq!(
+            random.next_gaussian().to_bits() as i64,
+            -4622281558612810903
+        );
+
+        let mut random = JavaRandom::new(-1);
+        assert_eq!(random.next_int(), 1155099827);
+        assert_eq!(random.next_int(), 1887904451);
+        assert_eq!(random.next_int_bounded(10), 9);
+        assert_eq!(random.next_int_bounded(16), 8);
+        assert_eq!(random.next_int_bounded(1073741825), 894294477);
+        assert_eq!(random.next_int_bounded(i32::MAX), 857465478);
+        assert_eq!(random.next_long(), -3206673117535979274);
+
+        let mut random = JavaRandom::new(9007199254740993);
+        random.next_int();
+        assert_eq!(random.next_int(), 431529176);
+        random.set_seed(42);
+        assert_eq!(random.next_int(), -1170105035);
+    }
+
+    #[test]
+    fn java_gaussians() {
+        // h = h * 31 + doubleToLongBits(nextGaussian()) over 100000 values of seed 7
+        let mut random = JavaRandom::new(7);
+        let hash = (0..100_000).fold(0i64, |hash, _| {
+            hash.wrapping_mul(31)
+                .wrapping_add(random.next_gaussian().to_bits() as i64)
+        });
+        assert_eq!(hash, -4067218162026060505);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bound_must_be_positive() {
+        JavaRandom::new(0).next_int_bounded(0);
+    }
+}
@@ -0,0 +1,138 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::{
+    common::{PacketNumber, Parse},
+    server::ServerToClient,
+};
+
+const HEADER: &str = "replay\t1";
+
+/// Everything players of one game were sent, as seen by a spectator.
+///
+/// Stored as text, a `replay\t1` header and then one event per line, the
+/// milliseconds since the game started followed by the packet as the server
+/// sends it, numbered `d 0`:
+///
+/// ```text
+/// replay\t1
+/// 0 d 0 game\tgameinfo\t-\tf\t0\t1\t1\t1\t20\t60\t0\t1\t0\t0\tf
+/// 0 d 0 game\tjoin\t0\tPlayer\t-
+/// 4012 d 0 game\tbeginstroke\t0\t70q4
+/// ```
+///
+/// `game\tchangescore` is recorded when a track ends and holds the strokes of
+/// every player on it, `-1` for players who gave up or left.
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub events: Vec<ReplayEvent>,
+}
+
+#[derive(Debug)]
+pub struct ReplayEvent {
+    /// Time since the game started.
+    pub at: Duration,
+    pub packet: ServerToClient,
+}
+
+impl ReplayEvent {
+    /// The packet as it goes on the wire with `packet_number`, including the newline.
+    pub fn line(&self, packet_number: PacketNumber) -> String {
+        let line = self.packet.as_string();
+        match line.strip_prefix("d 0 ") {
+            Some(rest) => format!("d {} {}", packet_number, rest),
+            None => line,
+        }
+    }
+}
+
+impl Replay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, at: Duration, packet: ServerToClient) {
+        self.events.push(ReplayEvent { at, packet });
+    }
+
+    /// How long the game went on.
+    pub fn duration(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |event| event.at)
+    }
+}
+
+impl FromStr for Replay {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        if lines.next() != Some(HEADER) {
+            bail!("not a replay");
+        }
+        let mut replay = Replay::new();
+        for (i, line) in lines.enumerate().filter(|(_, line)| !line.is_empty()) {
+            let (millis, packet) = line
+                .split_once(' ')
+                .with_context(|| format!("line {}: no time", i + 2))?;
+            let millis: u64 = millis
+                .parse()
+                .with_context(|| format!("line {}: bad time {:?}", i + 2, millis))?;
+            let (_, packet) = ServerToClient::parse(&format!("{}\n", packet))
+                .map_err(|e| anyhow!("line {}: {}", i + 2, e))?;
+            replay.push(Duration::from_millis(millis), packet);
+        }
+        Ok(replay)
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for event in &self.events {
+            write!(f, "{} {}", event.at.as_millis(), event.packet.as_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Replay;
+    use crate::{
+        common::{PacketNumber, Parse},
+        server::ServerToClient,
+    };
+
+    #[test]
+    fn replay_roundtrip() {
+        let text = "replay\t1\n\
+            0 d 0 game\tgameinfo\t-\tf\t0\t1\t1\t1\t20\t60\t0\t1\t0\t0\tf\n\
+            0 d 0 game\tjoin\t0\tPlayer\t-\n\
+            4012 d 0 game\tbeginstroke\t0\t70q4\n\
+            4800 d 0 game\tsay\t0\tnice\n";
+        let replay: Replay = text.parse().unwrap();
+        assert_eq!(replay.events.len(), 4);
+        assert!(matches!(
+            replay.events[2].packet,
+            ServerToClient::GameBeginStroke(_)
+        ));
+        assert_eq!(replay.duration(), Duration::from_millis(4800));
+        assert_eq!(replay.to_string(), text);
+        assert_eq!(
+            replay.events[3].line(PacketNumber(12)),
+            "d 12 game\tsay\t0\tnice\n"
+        );
+
+        assert!("replay\t2\n".parse::<Replay>().is_err());
+        assert!("replay\t1\nsoon d 0 game\tstart\n"
+            .parse::<Replay>()
+            .is_err());
+        let packet = ServerToClient::parse("d 0 game\tstart\n").unwrap().1;
+        let mut replay = Replay::new();
+        replay.push(Duration::from_millis(1), packet);
+        assert_eq!(replay.to_string(), "replay\t1\n1 d 0 game\tstart\n");
+    }
+}
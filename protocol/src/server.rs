@@ -10,17 +10,23 @@ use crate::common::NonEmptyOption;
 use crate::common::Packet;
 use crate::common::PacketNumber;
 use crate::common::Parse;
+use crate::common::PlayerInfo;
 use crate::common::Scoring;
 use crate::common::SomeAsTab;
+use crate::common::TrackTestMode;
 use crate::common::TrackType;
+use crate::common::Unknown;
 use crate::common::User;
 use crate::common::WaterEvent;
 use crate::common::WeightEnd;
+use crate::stroke::Stroke;
+use crate::track::Track;
 use nom::IResult;
 use parsemacro::Parse as ParseD;
 
 // H
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "h", space = true)]
 pub struct H {
     pub value: i32,
@@ -29,6 +35,7 @@ pub struct H {
 // S
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "s")]
 pub struct Version {
     pub value: String,
@@ -36,6 +43,7 @@ pub struct Version {
 // P
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "p kickban")]
 pub struct KickBan {
     pub value: KickStyle,
@@ -43,48 +51,57 @@ pub struct KickBan {
 // C
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c io", space = true)]
 pub struct Io {
     pub seed: i32,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c crt", space = true)]
 pub struct Crt {
     pub value: i32,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c ctr", space = true)]
 pub struct Ctr {}
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c id", space = true)]
 pub struct Id {
     pub value: usize,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c ping", space = true)]
 pub struct Ping {}
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c rcok", space = true)]
 pub struct Rcok {}
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "c rcf", space = true)]
 pub struct Rcf {}
 
 //D
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "versok")]
 pub struct VersOk {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "error")]
 pub struct Error {
     pub packet_number: PacketNumber,
@@ -92,6 +109,7 @@ pub struct Error {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "basicinfo")]
 pub struct BasicInfo {
     pub packet_number: PacketNumber,
@@ -102,12 +120,14 @@ pub struct BasicInfo {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "broadcast")]
 pub struct Broadcast {
     pub packet_number: PacketNumber,
     pub broadcast: String,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tgameinfo")]
 pub struct GameGameInfo {
     /*
@@ -131,12 +151,14 @@ pub struct GameGameInfo {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tplayers", opt_f = true)]
 pub struct GamePlayers {
     pub packet_number: PacketNumber,
     pub players: SomeAsTab<Vec<Player>>,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tend")]
 pub struct GameEnd {
     pub packet_number: PacketNumber,
@@ -144,6 +166,7 @@ pub struct GameEnd {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\towninfo")]
 pub struct GameOwnInfo {
     pub packet_number: PacketNumber,
@@ -153,12 +176,14 @@ pub struct GameOwnInfo {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tscoringmulti")]
 pub struct GameScoringMulti {
     pub packet_number: PacketNumber,
     pub scoring_multipliers: Vec<i32>,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tcr")]
 pub struct GameCr {
     pub packet_number: PacketNumber,
@@ -166,18 +191,21 @@ pub struct GameCr {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tchangescore")]
 pub struct GameChangeScore {
     pub packet_number: PacketNumber,
     pub scores: Vec<i32>,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tvoteskip")]
 pub struct GameVoteSkip {
     pub packet_number: PacketNumber,
     pub index: usize,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\trfng")] // ready for newgame
 pub struct GameRfng {
     pub packet_number: PacketNumber,
@@ -185,31 +213,40 @@ pub struct GameRfng {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tresetvoteskip")]
 pub struct GameResetVoteSkip {
     pub packet_number: PacketNumber,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tstarttrack")]
 pub struct GameStartTrack {
     pub packet_number: PacketNumber,
-    pub players: String, // t for every playing player or testmode ttm1, ttm2
-    //there could be 1 arg if testmode
+    pub players: PlayerInfo,
     pub seed: i32,
-    pub trackstrings: Vec<String>,
-    /*trackstring_2: String,
-    trackstring_3: String,
-    trackstring_4: String,
-    trackstring_5: String,
-    trackstring_6: String,
-    trackstring_7: String,*/
+    #[parse(rest)]
+    pub track: Track,
 }
+/// `game\tstarttrack` of a track test mode game, see [`TrackTestMode`].
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[parse(tag = "game\tstarttrack")]
+pub struct GameStartTestTrack {
+    pub packet_number: PacketNumber,
+    pub mode: TrackTestMode,
+    pub seed: i32,
+    #[parse(rest)]
+    pub track: Track,
+}
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tgame")]
 pub struct GameGame {
     pub packet_number: PacketNumber,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tstartturn")]
 pub struct GameStartTurn {
     pub packet_number: PacketNumber,
@@ -217,12 +254,14 @@ pub struct GameStartTurn {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tstart")]
 pub struct GameStart {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tsay")]
 pub struct GameSay {
     pub packet_number: PacketNumber,
@@ -230,6 +269,7 @@ pub struct GameSay {
     pub message: String,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tpart")]
 pub struct GamePart {
     pub packet_number: PacketNumber,
@@ -237,6 +277,7 @@ pub struct GamePart {
     pub reason: usize,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tjoin")]
 pub struct GameJoin {
     pub packet_number: PacketNumber,
@@ -245,14 +286,48 @@ pub struct GameJoin {
     pub clan: NonEmptyOption<String>,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "game\tbeginstroke")]
 pub struct GameBeginStroke {
     pub packet_number: PacketNumber,
     pub index: usize,
-    pub coords: String,
+    pub coords: Stroke,
+}
+
+/// Relays the `game\tendstroke` of player `index` to the other players.
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[parse(tag = "game\tendstroke")]
+pub struct GameEndStroke {
+    pub packet_number: PacketNumber,
+    pub index: usize,
+    pub in_hole: PlayerInfo,
+}
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[parse(tag = "game\tbacktoprivate")]
+pub struct GameBackToPrivate {
+    pub packet_number: PacketNumber,
+    pub index: usize,
+}
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[parse(tag = "game\trejectaccept")]
+pub struct GameRejectAccept {
+    pub packet_number: PacketNumber,
+    pub track: i32,
+    pub value: bool,
+}
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[parse(tag = "game\tsherifsay")]
+pub struct GameSheriffSay {
+    pub packet_number: PacketNumber,
+    pub message: String,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "status\tlogin", notab = true)]
 pub struct StatusLogin {
     pub packet_number: PacketNumber,
@@ -260,12 +335,14 @@ pub struct StatusLogin {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "status\tgame")]
 pub struct StatusGame {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "status\tlobby")]
 pub struct StatusLobby {
     pub packet_number: PacketNumber,
@@ -273,6 +350,7 @@ pub struct StatusLobby {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "status\tlobbyselect")]
 pub struct StatusLobbySelect {
     pub packet_number: PacketNumber,
@@ -280,12 +358,22 @@ pub struct StatusLobbySelect {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\ttracksetlist")]
 pub struct LobbyTrackSetlist {
     pub packet_number: PacketNumber,
     pub setlist: Option<Vec<Tracklist>>,
 }
+/// Names of the tracks a solo player can pick for `lobby\tcspc`.
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[parse(tag = "lobby\ttracklist")]
+pub struct LobbyTracklist {
+    pub packet_number: PacketNumber,
+    pub tracks: Option<Vec<String>>,
+}
+#[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tnumberofusers")]
 pub struct LobbyNumberOfUsers {
     pub packet_number: PacketNumber,
@@ -297,6 +385,7 @@ pub struct LobbyNumberOfUsers {
     pub multi_playing: i32,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\townjoin")]
 pub struct LobbyOwnJoin {
     pub packet_number: PacketNumber,
@@ -304,6 +393,7 @@ pub struct LobbyOwnJoin {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tjoinfromgame")]
 pub struct LobbyJoinFromGame {
     pub packet_number: PacketNumber,
@@ -311,30 +401,35 @@ pub struct LobbyJoinFromGame {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tjoin")]
 pub struct LobbyJoin {
     pub packet_number: PacketNumber,
     pub user: User,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tcfail")]
 pub struct LobbyCFail {
     pub packet_number: PacketNumber,
     pub reason: DChallengeFail,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tafail")]
 pub struct LobbyAFail {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tcancel")]
 pub struct LobbyCancel {
     pub packet_number: PacketNumber,
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tchallenge")]
 pub struct LobbyChallenge {
     pub packet_number: PacketNumber,
@@ -349,6 +444,7 @@ pub struct LobbyChallenge {
     pub track_scoring_weighted_end: WeightEnd,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tnc")]
 pub struct LobbyNC {
     pub packet_number: PacketNumber,
@@ -356,6 +452,7 @@ pub struct LobbyNC {
     pub no_challenges: bool,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tsherifsay")]
 pub struct LobbySheriffSay {
     pub packet_number: PacketNumber,
@@ -363,6 +460,7 @@ pub struct LobbySheriffSay {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tsay")]
 pub struct LobbySay {
     pub packet_number: PacketNumber,
@@ -371,6 +469,7 @@ pub struct LobbySay {
     pub message: String,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tsayp")]
 pub struct LobbySayP {
     pub packet_number: PacketNumber,
@@ -379,6 +478,7 @@ pub struct LobbySayP {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tgsn")] //game starts duo
 pub struct LobbyGsn {
     pub packet_number: PacketNumber,
@@ -387,6 +487,7 @@ pub struct LobbyGsn {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tusers", opt_f = true)]
 pub struct LobbyUsers {
     pub packet_number: PacketNumber,
@@ -396,16 +497,17 @@ pub struct LobbyUsers {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tpart")]
 pub struct LobbyPart {
     pub packet_number: PacketNumber,
     // "d 17 lobby\tpart\tzocker666\t2\t#1583093"
     pub name: String,
     pub reason: JoinLeaveReason,
-    //TODO
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tgamelist\tfull")]
 pub struct LobbyGamelistFull {
     pub packet_number: PacketNumber,
@@ -414,6 +516,7 @@ pub struct LobbyGamelistFull {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "", notab = true, notag = true)]
 pub struct Tracklist {
     name: String,
@@ -432,6 +535,7 @@ pub struct Tracklist {
     day_best_strokes: i32,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "", notab = true, notag = true)]
 pub struct Game {
     pub id: usize,
@@ -453,6 +557,7 @@ pub struct Game {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "", notab = true, notag = true)]
 pub struct Player {
     pub index: usize,
@@ -460,12 +565,14 @@ pub struct Player {
     pub clan: NonEmptyOption<String>,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tgamelist\tremove")]
 pub struct LobbyGamelistRemove {
     pub packet_number: PacketNumber,
     pub id: usize,
 }
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tgamelist\tchange")]
 pub struct LobbyGamelistChange {
     pub packet_number: PacketNumber,
@@ -473,6 +580,7 @@ pub struct LobbyGamelistChange {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobby\tgamelist\tadd")]
 pub struct LobbyGamelistAdd {
     pub packet_number: PacketNumber,
@@ -480,6 +588,7 @@ pub struct LobbyGamelistAdd {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobbyselect\tnop")] // Number of players
 pub struct LobbySelectNop {
     pub packet_number: PacketNumber,
@@ -489,6 +598,7 @@ pub struct LobbySelectNop {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[parse(tag = "lobbyselect\tlobby")]
 pub struct LobbySelectLobby {
     pub packet_number: PacketNumber,
@@ -496,6 +606,8 @@ pub struct LobbySelectLobby {
 }
 
 #[derive(Debug, ParseD)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "data"))]
 pub enum ServerToClient {
     GameGameInfo(GameGameInfo), //behind gamegame
     H(H),
@@ -525,13 +637,19 @@ pub enum ServerToClient {
     GameSay(GameSay),
     GameJoin(GameJoin),
     GameStartTrack(GameStartTrack),
+    GameStartTestTrack(GameStartTestTrack),
     GameStartTurn(GameStartTurn),
     GameBeginStroke(GameBeginStroke),
+    GameEndStroke(GameEndStroke),
+    GameBackToPrivate(GameBackToPrivate),
+    GameRejectAccept(GameRejectAccept),
+    GameSheriffSay(GameSheriffSay),
     StatusLogin(StatusLogin),
     StatusGame(StatusGame),
     StatusLobby(StatusLobby),
     StatusLobbySelect(StatusLobbySelect),
     LobbyTrackSetlist(LobbyTrackSetlist),
+    LobbyTracklist(LobbyTracklist),
     LobbyNumberOfUsers(LobbyNumberOfUsers),
     LobbyOwnJoin(LobbyOwnJoin),
     LobbyJoinFromGame(LobbyJoinFromGame),
@@ -558,15 +676,26 @@ pub enum ServerToClient {
     LobbySelectLobby(LobbySelectLobby),
     GameGame(GameGame),
     GameStart(GameStart),
+    #[parse(fallback)]
+    Unknown(Unknown),
 }
 
 #[cfg(test)]
 mod tests {
 
-    use std::assert_matches::assert_matches;
+    use std::assert_matches;
+
+    use super::{GameGameInfo, ServerToClient};
+    use crate::{
+        arbitrary::assert_roundtrip,
+        common::{DLoginStatus, Packet, PacketNumber, Parse, Unknown},
+        server::LobbySayP,
+    };
+    #[test]
+    fn roundtrip_test() {
+        assert_roundtrip::<ServerToClient>(10_000);
+    }
 
-    use super::GameGameInfo;
-    use crate::{common::Parse, server::LobbySayP};
     #[test]
     fn gameinfo_test() {
         let str = "d 9 game\tgameinfo\t-\tf\t13\t3\t10\t1\t20\t60\t0\t1\t0\t0\tf\n";
@@ -574,9 +703,137 @@ mod tests {
         assert_eq!(GameGameInfo::parse(&str).unwrap().1.as_string(), str);
     }
 
+    #[test]
+    fn game_packets_test() {
+        // Written from the field layouts, there are no captured lines of these yet
+        let lines = [
+            "d 14 game\tendstroke\t1\ttft\n",
+            "d 15 game\tbacktoprivate\t0\n",
+            "d 16 game\trejectaccept\t3\tf\n",
+            "d 17 game\tsherifsay\tbe nice\n",
+            "d 18 lobby\ttracklist\tHole in one\tUphill\n",
+        ];
+        for line in lines {
+            let packet = ServerToClient::parse(line).unwrap().1;
+            assert!(!matches!(packet, ServerToClient::Unknown(_)), "{:?}", line);
+            assert_eq!(packet.as_string(), line);
+        }
+
+        let track = crate::track::tests::TEST_TRACK
+            .lines()
+            .collect::<Vec<_>>()
+            .join("\t");
+        let line = format!("d 8 game\tstarttrack\tttm1\t12345\t{}\n", track);
+        let packet = ServerToClient::parse(&line).unwrap().1;
+        assert_matches!(&packet, ServerToClient::GameStartTestTrack(start) if start.mode.0 == 1);
+        assert_eq!(packet.as_string(), line);
+    }
+
     #[test]
     fn chat_test() {
         let input = "d 5 lobby	sayp	Nokkasiili	lol lol lol\n";
         assert_eq!(LobbySayP::parse(input).unwrap().1.as_string(), input);
     }
+
+    #[test]
+    fn unknown_test() {
+        let input = "d 12 lobby\tfoo\t\tbar\t\nd 03 x\nc mystery 1\n\nh 1\n";
+        let mut rest = input;
+        let mut output = String::new();
+        let mut unknowns = Vec::new();
+        while !rest.is_empty() {
+            let (input, packet) = ServerToClient::parse(rest).unwrap();
+            output.push_str(&packet.as_string());
+            if let ServerToClient::Unknown(unknown) = packet {
+                unknowns.push(unknown);
+            }
+            rest = input;
+        }
+        assert_eq!(output, input);
+        assert_eq!(unknowns.len(), 4);
+        assert_eq!(
+            unknowns[0],
+            Unknown {
+                packet_number: Some(PacketNumber(12)),
+                tag: "lobby".to_string(),
+                fields: vec!["foo".into(), "".into(), "bar".into(), "".into()],
+            }
+        );
+        assert_eq!(unknowns[1].packet_number, None);
+        assert_eq!(unknowns[2].tag, "c mystery 1");
+
+        // A known tag with extra fields is that packet with data left over
+        let (rest, packet) = ServerToClient::parse("d 5 lobby\tsayp\ta\tb\tc\n").unwrap();
+        assert_matches!(packet, ServerToClient::LobbySayP(_));
+        assert_eq!(packet.packet_number(), Some(PacketNumber(5)));
+        assert_eq!(rest, "\tc\n");
+    }
+
+    #[test]
+    fn status_login_test() {
+        for (input, status) in [
+            (
+                "d 1 status\tlogin\tnickinuse\n",
+                Some(DLoginStatus::NickInUse),
+            ),
+            ("d 1 status\tlogin\n", None),
+            (
+                "d 1 status\tlogin\tbanned\n",
+                Some(DLoginStatus::Other("banned".to_string())),
+            ),
+        ] {
+            let packet = ServerToClient::parse(input).unwrap().1;
+            assert_matches!(&packet, ServerToClient::StatusLogin(login) if login.status.0 == status);
+            assert_eq!(packet.as_string(), input);
+        }
+    }
+
+    #[test]
+    fn starttrack_and_part_test() {
+        let track = crate::track::tests::TEST_TRACK
+            .lines()
+            .collect::<Vec<_>>()
+            .join("\t");
+        let input = format!("d 8 game\tstarttrack\ttt\t12345\t{}\n", track);
+        let packet = ServerToClient::parse(&input).unwrap().1;
+        assert_matches!(&packet, ServerToClient::GameStartTrack(start) if start.seed == 12345 && start.track.name == "Test");
+        assert_eq!(packet.as_string(), input);
+
+        for input in [
+            "d 17 lobby\tpart\tzocker666\t2\t#1583093\n",
+            "d 18 lobby\tpart\tzocker666\t4\n",
+        ] {
+            let packet = ServerToClient::parse(input).unwrap().1;
+            assert_matches!(packet, ServerToClient::LobbyPart(_));
+            assert_eq!(packet.as_string(), input);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_test() {
+        let input = "d 5 lobby\tsayp\tNokkasiili\tlol\n";
+        let packet = ServerToClient::parse(input).unwrap().1;
+        let json = serde_json::to_value(&packet).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "LobbySayP",
+                "data": { "packet_number": 5, "from": "Nokkasiili", "message": "lol" }
+            })
+        );
+        let packet: ServerToClient = serde_json::from_value(json).unwrap();
+        assert_eq!(packet.as_string(), input);
+
+        for input in [
+            "d 7 lobby\tusers\t3:~anonym-2893^wn^-1^de_DE^-^-\t3:Jomppppa^rn^146^fi_FI^-^-\n",
+            "d 9 game\tgameinfo\t-\tf\t13\t3\t10\t1\t20\t60\t0\t1\t0\t0\tf\n",
+            "d 12 lobby\tfoo\t\tbar\n",
+        ] {
+            let packet = ServerToClient::parse(input).unwrap().1;
+            let json = serde_json::to_string(&packet).unwrap();
+            let packet: ServerToClient = serde_json::from_str(&json).unwrap();
+            assert_eq!(packet.as_string(), input);
+        }
+    }
 }
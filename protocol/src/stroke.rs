@@ -0,0 +1,120 @@
+use core::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use nom::bytes::complete::take;
+use nom::combinator::map_res;
+use nom::IResult;
+
+use crate::common::Parse;
+use crate::track::map::{MAP_HEIGHT, MAP_WIDTH, TILE_SIZE};
+
+/// Width of the track in pixels.
+pub const TRACK_WIDTH: u16 = (MAP_WIDTH * TILE_SIZE) as u16;
+/// Height of the track in pixels.
+pub const TRACK_HEIGHT: u16 = (MAP_HEIGHT * TILE_SIZE) as u16;
+/// Number of shooting modes the client cycles through with the right mouse button.
+pub const STROKE_MODES: u8 = 4;
+
+const ENCODED_LEN: usize = 4;
+
+/// A shot as sent in `game\tbeginstroke`.
+///
+/// The client sends the point the mouse was clicked at, the ball position and the
+/// power follow from the game state. It encodes `(x * 375 + y) * 4 + mode` as four
+/// base 36 digits, so `70q4` is a normal shot aimed at `218,133`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stroke {
+    pub x: u16,
+    pub y: u16,
+    /// Shooting mode, 0 is a normal shot.
+    pub mode: u8,
+}
+
+impl Stroke {
+    pub fn new(x: u16, y: u16, mode: u8) -> anyhow::Result<Self> {
+        if x >= TRACK_WIDTH || y >= TRACK_HEIGHT {
+            bail!("stroke aimed outside of the track at {},{}", x, y);
+        }
+        if mode >= STROKE_MODES {
+            bail!("invalid stroke mode {}", mode);
+        }
+        Ok(Stroke { x, y, mode })
+    }
+
+    fn code(&self) -> u32 {
+        (self.x as u32 * TRACK_HEIGHT as u32 + self.y as u32) * STROKE_MODES as u32
+            + self.mode as u32
+    }
+}
+
+impl FromStr for Stroke {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // from_str_radix would also take uppercase and a sign
+        if s.len() != ENCODED_LEN || !s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'z')) {
+            bail!("invalid stroke {:?}", s);
+        }
+        let code = u32::from_str_radix(s, 36).with_context(|| format!("invalid stroke {:?}", s))?;
+        let mode = code % STROKE_MODES as u32;
+        let position = code / STROKE_MODES as u32;
+        let x = position / TRACK_HEIGHT as u32;
+        let y = position % TRACK_HEIGHT as u32;
+        if x >= TRACK_WIDTH as u32 {
+            bail!("stroke {:?} aimed outside of the track", s);
+        }
+        Stroke::new(x as u16, y as u16, mode as u8)
+    }
+}
+
+impl Display for Stroke {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut code = self.code();
+        let mut digits = [b'0'; ENCODED_LEN];
+        for digit in digits.iter_mut().rev() {
+            *digit = char::from_digit(code % 36, 36).unwrap() as u8;
+            code /= 36;
+        }
+        write!(f, "{}", std::str::from_utf8(&digits).unwrap())
+    }
+}
+
+impl Parse for Stroke {
+    fn parse(input: &str) -> IResult<&str, Self> {
+        map_res(take(ENCODED_LEN), str::parse)(input)
+    }
+
+    fn as_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Stroke, TRACK_HEIGHT, TRACK_WIDTH};
+    use crate::common::Parse;
+
+    #[test]
+    fn decode_stroke() {
+        let stroke: Stroke = "70q4".parse().unwrap();
+        assert_eq!(stroke, Stroke::new(218, 133, 0).unwrap());
+        assert_eq!(stroke.as_string(), "70q4");
+
+        let corner = Stroke::new(TRACK_WIDTH - 1, TRACK_HEIGHT - 1, 3).unwrap();
+        assert_eq!(Stroke::parse(&corner.as_string()).unwrap().1, corner);
+        assert_eq!(Stroke::new(0, 0, 0).unwrap().as_string(), "0000");
+    }
+
+    #[test]
+    fn reject_invalid_strokes() {
+        for input in ["", "70q", "70Q4", "-0q4", "zzzz", "70q4x"] {
+            assert!(input.parse::<Stroke>().is_err(), "{:?}", input);
+        }
+        assert!(Stroke::new(TRACK_WIDTH, 0, 0).is_err());
+        assert!(Stroke::new(0, TRACK_HEIGHT, 0).is_err());
+        assert!(Stroke::new(0, 0, 4).is_err());
+    }
+}
@@ -0,0 +1,356 @@
+use core::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context};
+use nom::combinator::map_res;
+use nom::IResult;
+
+use crate::common::{Parse, TrackType};
+
+pub mod map;
+
+use map::TrackMap;
+
+/// A track as sent in `game\tstarttrack`, one prefixed line per field.
+///
+/// ```text
+/// V 1
+/// A Nokkis
+/// N Test
+/// T BA2Q47D...,Ads:A2309B2208C4019
+/// S fttf14
+/// C 3,4
+/// I 13942,90651,1,37
+/// R 94,12,23,28,28,77,67,49,33,31,279
+/// B igo,1283637600000
+/// L igo,1283637600000
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Track {
+    pub version: i32,
+    pub author: String,
+    pub name: String,
+    pub map: String,
+    pub ads: Option<String>,
+    pub settings: Option<TrackSettings>,
+    pub categories: Vec<TrackType>,
+    pub stats: Option<TrackStats>,
+    pub ratings: Option<Vec<u32>>,
+    pub best: Option<TrackRecord>,
+    pub last: Option<TrackRecord>,
+}
+
+/// `S` line, four flags followed by the minimum and maximum number of players.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackSettings {
+    pub mines_visible: bool,
+    pub magnets_visible: bool,
+    pub teleport_colors: bool,
+    pub illusion_shadows: bool,
+    pub min_players: u8,
+    pub max_players: u8,
+}
+
+/// `I` line.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackStats {
+    pub plays: u32,
+    pub strokes: u32,
+    pub best_par: u32,
+    pub best_par_count: u32,
+}
+
+/// `B` and `L` lines, the first and the latest player to reach the best par.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackRecord {
+    pub player: String,
+    pub timestamp: i64,
+}
+
+impl Track {
+    pub fn from_lines<I, S>(lines: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut version = None;
+        let mut author = None;
+        let mut name = None;
+        let mut map = None;
+        let mut track = Track::default();
+
+        for line in lines {
+            let line = line.as_ref().trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                continue;
+            }
+            let (prefix, value) = line
+                .split_once(' ')
+                .with_context(|| format!("invalid track line {:?}", line))?;
+            match prefix {
+                "V" => version = Some(value.parse().context("invalid version")?),
+                "A" => author = Some(value.to_string()),
+                "N" => name = Some(value.to_string()),
+                "T" => map = Some(value.to_string()),
+                "S" => track.settings = Some(value.parse()?),
+                "C" => track.categories = parse_categories(value)?,
+                "I" => track.stats = Some(value.parse()?),
+                "R" => track.ratings = Some(parse_list(value).context("invalid ratings")?),
+                "B" => track.best = Some(value.parse()?),
+                "L" => track.last = Some(value.parse()?),
+                _ => bail!("unknown track line {:?}", line),
+            }
+        }
+
+        track.version = version.context("track has no version")?;
+        track.author = author.context("track has no author")?;
+        track.name = name.context("track has no name")?;
+        let map = map.context("track has no map data")?;
+        match map.split_once(",Ads:") {
+            Some((map, ads)) => {
+                track.map = map.to_string();
+                track.ads = Some(ads.to_string());
+            }
+            None => track.map = map,
+        }
+        Ok(track)
+    }
+
+    pub fn decode_map(&self) -> anyhow::Result<TrackMap> {
+        TrackMap::decode(&self.map)
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("V {}", self.version),
+            format!("A {}", self.author),
+            format!("N {}", self.name),
+        ];
+        match &self.ads {
+            Some(ads) => lines.push(format!("T {},Ads:{}", self.map, ads)),
+            None => lines.push(format!("T {}", self.map)),
+        }
+        if let Some(settings) = &self.settings {
+            lines.push(format!("S {}", settings));
+        }
+        if !self.categories.is_empty() {
+            let categories: Vec<_> = self.categories.iter().map(|c| c.as_string()).collect();
+            lines.push(format!("C {}", categories.join(",")));
+        }
+        if let Some(stats) = &self.stats {
+            lines.push(format!("I {}", stats));
+        }
+        if let Some(ratings) = &self.ratings {
+            let ratings: Vec<_> = ratings.iter().map(|r| r.to_string()).collect();
+            lines.push(format!("R {}", ratings.join(",")));
+        }
+        if let Some(best) = &self.best {
+            lines.push(format!("B {}", best));
+        }
+        if let Some(last) = &self.last {
+            lines.push(format!("L {}", last));
+        }
+        lines
+    }
+}
+
+fn parse_list<T: FromStr>(input: &str) -> anyhow::Result<Vec<T>> {
+    input
+        .split(',')
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| anyhow!("invalid value {:?}", value))
+        })
+        .collect()
+}
+
+fn parse_categories(input: &str) -> anyhow::Result<Vec<TrackType>> {
+    input
+        .split(',')
+        .map(|value| match TrackType::parse(value) {
+            Ok(("", category)) => Ok(category),
+            _ => Err(anyhow!("invalid category {:?}", value)),
+        })
+        .collect()
+}
+
+impl FromStr for Track {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Track::from_lines(s.lines())
+    }
+}
+
+impl Display for Track {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.lines() {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Tracks travel as tab separated lines inside `game\tstarttrack`.
+impl Parse for Track {
+    fn parse(input: &str) -> IResult<&str, Self> {
+        map_res(Vec::<String>::parse, Track::from_lines)(input)
+    }
+
+    fn as_string(&self) -> String {
+        self.lines().join("\t")
+    }
+}
+
+impl FromStr for TrackSettings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let flag = |i: usize| match s.as_bytes().get(i) {
+            Some(b't') => Ok(true),
+            Some(b'f') => Ok(false),
+            _ => Err(anyhow!("invalid settings {:?}", s)),
+        };
+        let players = |i: usize| match s.as_bytes().get(i) {
+            Some(c @ b'0'..=b'9') => Ok(c - b'0'),
+            _ => Err(anyhow!("invalid settings {:?}", s)),
+        };
+        if s.len() != 6 {
+            bail!("invalid settings {:?}", s);
+        }
+        Ok(TrackSettings {
+            mines_visible: flag(0)?,
+            magnets_visible: flag(1)?,
+            teleport_colors: flag(2)?,
+            illusion_shadows: flag(3)?,
+            min_players: players(4)?,
+            max_players: players(5)?,
+        })
+    }
+}
+
+impl Display for TrackSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for flag in [
+            self.mines_visible,
+            self.magnets_visible,
+            self.teleport_colors,
+            self.illusion_shadows,
+        ] {
+            write!(f, "{}", flag.as_string())?;
+        }
+        write!(f, "{}{}", self.min_players, self.max_players)
+    }
+}
+
+impl FromStr for TrackStats {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_list(s).context("invalid stats")?[..] {
+            [plays, strokes, best_par, best_par_count] => Ok(TrackStats {
+                plays,
+                strokes,
+                best_par,
+                best_par_count,
+            }),
+            _ => bail!("invalid stats {:?}", s),
+        }
+    }
+}
+
+impl Display for TrackStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.plays, self.strokes, self.best_par, self.best_par_count
+        )
+    }
+}
+
+impl FromStr for TrackRecord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (player, timestamp) = s
+            .rsplit_once(',')
+            .with_context(|| format!("invalid record {:?}", s))?;
+        Ok(TrackRecord {
+            player: player.to_string(),
+            timestamp: timestamp.parse().context("invalid record timestamp")?,
+        })
+    }
+}
+
+impl Display for TrackRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.player, self.timestamp)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{Track, TrackRecord, TrackSettings};
+    use crate::common::{Parse, TrackType};
+
+    pub const TEST_TRACK: &str = "V 1\nA Nokkis\nN Test\nT BA2Q47DCUAECYABA2VCZAGCaAGCbAGC2AB3A36DCBAFEBCWABA2W5GEB3A38D2EB3A46D2EBA2DBABDBACDE40DBWQABA2Q2D5E17DCWI3DE8DCXTDE9DCOA6E14DCWI2DBAMABANABAOABAPAE6DCWTDF2E7D2H2D5E14DBAIABAKAGI10DEG5DC2DBA2NBATDE3DCMA6E11DCE3D4E17DCDCBAMN2ED2H2D5E14D4E17DCD2BAON2E3DCKA6E16D2E17DCDABAPN2ED2H2D5E14DBAKA2DE3DBQAT4DE15DCIA6E20DBIATBA2Q4DCDABJATE11DBPAQH2D5E19DBU2ACDABAGQ3DBAHQBAIQBA2QBRATE12DCJA6E19DBTATBA2QBAFQDBASQD5E10D2H2D5E19D2EBAEQBASQBbASBYASF4E12DCLA6E19D4EB3AD5E10D2H2D5E19D4EBVASD5E12DCNA6E5DCG3DBUASE9D4EHD5E10D2H2D5E19D4EBaASBZAS5E12DCPA6E13DBWMAE4D3EBALQFDBAJQD3E10D2H2D5E13D2E4D4EBAKQ3DCDABU2AE7DB2AQ2DFGD6E13D2E5DBLATCDAI4DBKATB3A4DB2AQE8DECDA2E2CADE12D2E6DBU2ABSAT4DB3AB2AQ4DF3DCT2DCSACQPDCRAECVAFI29DBAR4DBA2Q12D,Ads:A2309B2208C4019\nS fttf14\nC 3,4\nI 13942,90651,1,37\nR 94,12,23,28,28,77,67,49,33,31,279\nB igo,1283637600000\nL igo,1283637600000\n";
+
+    #[test]
+    fn track_round_trip() {
+        let track: Track = TEST_TRACK.parse().unwrap();
+        assert_eq!(track.version, 1);
+        assert_eq!(track.author, "Nokkis");
+        assert_eq!(track.name, "Test");
+        assert!(track.map.starts_with("BA2Q47D"));
+        assert_eq!(track.ads.as_deref(), Some("A2309B2208C4019"));
+        assert_eq!(
+            track.settings,
+            Some(TrackSettings {
+                mines_visible: false,
+                magnets_visible: true,
+                teleport_colors: true,
+                illusion_shadows: false,
+                min_players: 1,
+                max_players: 4,
+            })
+        );
+        assert_eq!(
+            track.categories,
+            vec![TrackType::Modern, TrackType::HoleInOne]
+        );
+        assert_eq!(track.stats.unwrap().best_par, 1);
+        assert_eq!(track.ratings.as_ref().unwrap().len(), 11);
+        assert_eq!(
+            track.best,
+            Some(TrackRecord {
+                player: "igo".to_string(),
+                timestamp: 1283637600000,
+            })
+        );
+        assert_eq!(track.to_string(), TEST_TRACK);
+    }
+
+    #[test]
+    fn track_parse_tabs() {
+        let input = TEST_TRACK.trim_end().replace('\n', "\t") + "\n";
+        let (rest, track) = Track::parse(&input).unwrap();
+        assert_eq!(rest, "\n");
+        assert_eq!(format!("{}\n", track.as_string()), input);
+    }
+
+    #[test]
+    fn track_minimal() {
+        let track: Track = "V 1\nA a\nN b\nT BAAA\n".parse().unwrap();
+        assert_eq!(track.ads, None);
+        assert_eq!(track.lines(), vec!["V 1", "A a", "N b", "T BAAA"]);
+        assert!("V 1\nA a\nT BAAA\n".parse::<Track>().is_err());
+        assert!("V 1\nA a\nN b\nT BAAA\nS ftf14\n".parse::<Track>().is_err());
+    }
+}
@@ -0,0 +1,510 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+
+pub const MAP_WIDTH: usize = 49;
+pub const MAP_HEIGHT: usize = 25;
+/// Size of a tile in pixels, the client draws the map as 735x375 pixels.
+pub const TILE_SIZE: usize = 15;
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// Longest possible expanded map, every tile written out as a `B` tile.
+const MAX_EXPANDED_LEN: usize = MAP_WIDTH * MAP_HEIGHT * 4;
+
+/// Back references in the order the track editor tries them:
+/// `D` left, `E` up, `F` up-left, `G` two left, `H` two up, `I` two up-left.
+const COPY_OFFSETS: [(u8, usize, usize); 6] = [
+    (b'D', 1, 0),
+    (b'E', 0, 1),
+    (b'F', 1, 1),
+    (b'G', 2, 0),
+    (b'H', 0, 2),
+    (b'I', 2, 2),
+];
+
+/// Ground elements, used both as tile backgrounds and foregrounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Element {
+    Grass,
+    Dirt,
+    Mud,
+    Ice,
+    /// Slope pushing the ball towards `direction`, 0 is north and steps are 45 degrees clockwise.
+    Downhill {
+        direction: u8,
+    },
+    Water,
+    Acid,
+    WaterSwamp,
+    AcidSwamp,
+    Block,
+    StickyBlock,
+    BouncyBlock,
+    /// A code without a known meaning, kept as is. The bundled test track uses
+    /// 19, 21 and 22.
+    Other(u8),
+}
+
+impl Element {
+    pub fn is_wall(&self) -> bool {
+        matches!(
+            self,
+            Element::Block | Element::StickyBlock | Element::BouncyBlock
+        )
+    }
+
+    pub fn is_liquid(&self) -> bool {
+        matches!(
+            self,
+            Element::Water | Element::Acid | Element::WaterSwamp | Element::AcidSwamp
+        )
+    }
+}
+
+impl From<u8> for Element {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Element::Grass,
+            1 => Element::Dirt,
+            2 => Element::Mud,
+            3 => Element::Ice,
+            4..=11 => Element::Downhill {
+                direction: value - 4,
+            },
+            12 => Element::Water,
+            13 => Element::Acid,
+            14 => Element::WaterSwamp,
+            15 => Element::AcidSwamp,
+            16 => Element::Block,
+            17 => Element::StickyBlock,
+            18 => Element::BouncyBlock,
+            _ => Element::Other(value),
+        }
+    }
+}
+
+impl From<Element> for u8 {
+    fn from(value: Element) -> Self {
+        match value {
+            Element::Grass => 0,
+            Element::Dirt => 1,
+            Element::Mud => 2,
+            Element::Ice => 3,
+            Element::Downhill { direction } => 4 + direction,
+            Element::Water => 12,
+            Element::Acid => 13,
+            Element::WaterSwamp => 14,
+            Element::AcidSwamp => 15,
+            Element::Block => 16,
+            Element::StickyBlock => 17,
+            Element::BouncyBlock => 18,
+            Element::Other(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    Blue,
+    Red,
+    Yellow,
+    Green,
+}
+
+impl Color {
+    pub const ALL: [Color; 4] = [Color::Blue, Color::Red, Color::Yellow, Color::Green];
+}
+
+/// Objects placed with `C` tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Special {
+    Start,
+    Hole,
+    FakeHole,
+    MovableBlock,
+    Mine {
+        big: bool,
+        exploded: bool,
+    },
+    TeleportStart(Color),
+    TeleportExit(Color),
+    BreakableBlock {
+        damage: u8,
+    },
+    MagnetAttract,
+    MagnetRepel,
+    PlayerStart(Color),
+    /// A code without a known meaning, kept as is. The bundled test track uses
+    /// 22 and 23.
+    Other(u8),
+}
+
+impl From<u8> for Special {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Special::Start,
+            1 => Special::Hole,
+            2 => Special::FakeHole,
+            3 => Special::MovableBlock,
+            4..=7 => Special::Mine {
+                big: value >= 6,
+                exploded: !value.is_multiple_of(2),
+            },
+            8..=15 if value.is_multiple_of(2) => {
+                Special::TeleportStart(Color::ALL[(value as usize - 8) / 2])
+            }
+            8..=15 => Special::TeleportExit(Color::ALL[(value as usize - 8) / 2]),
+            16..=19 => Special::BreakableBlock { damage: value - 16 },
+            20 => Special::MagnetAttract,
+            21 => Special::MagnetRepel,
+            24..=27 => Special::PlayerStart(Color::ALL[value as usize - 24]),
+            _ => Special::Other(value),
+        }
+    }
+}
+
+impl From<Special> for u8 {
+    fn from(value: Special) -> Self {
+        match value {
+            Special::Start => 0,
+            Special::Hole => 1,
+            Special::FakeHole => 2,
+            Special::MovableBlock => 3,
+            Special::Mine { big, exploded } => 4 + 2 * big as u8 + exploded as u8,
+            Special::TeleportStart(color) => 8 + 2 * color as u8,
+            Special::TeleportExit(color) => 9 + 2 * color as u8,
+            Special::BreakableBlock { damage } => 16 + damage,
+            Special::MagnetAttract => 20,
+            Special::MagnetRepel => 21,
+            Special::PlayerStart(color) => 24 + color as u8,
+            Special::Other(value) => value,
+        }
+    }
+}
+
+/// One 15x15 pixel cell of the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tile {
+    /// `A` tile, never written by the track editor but accepted by the client.
+    Empty { shape: u8, foreground: u8 },
+    /// `B` tile, `shape` selects which pixels show `foreground` on top of `background`.
+    Normal {
+        shape: u8,
+        foreground: u8,
+        background: u8,
+    },
+    /// `C` tile, a special object drawn on top of `background`.
+    Special { special: Special, background: u8 },
+}
+
+impl Default for Tile {
+    fn default() -> Self {
+        Tile::Normal {
+            shape: 0,
+            foreground: 0,
+            background: 0,
+        }
+    }
+}
+
+impl Tile {
+    pub fn special(&self) -> Option<Special> {
+        match self {
+            Tile::Special { special, .. } => Some(*special),
+            _ => None,
+        }
+    }
+
+    pub fn background(&self) -> Element {
+        match self {
+            Tile::Empty { foreground, .. } => Element::from(*foreground),
+            Tile::Normal { background, .. } => Element::from(*background),
+            Tile::Special { background, .. } => Element::from(*background),
+        }
+    }
+
+    /// Fails for values the map alphabet has no character for, 52 and up.
+    fn encode(&self, output: &mut Vec<u8>) -> anyhow::Result<()> {
+        let c = |value: u8| {
+            ALPHABET
+                .get(value as usize)
+                .copied()
+                .with_context(|| format!("{} doesn't fit in a map character", value))
+        };
+        match *self {
+            Tile::Empty { shape, foreground } => {
+                output.extend([b'A', c(shape)?, c(foreground)?]);
+            }
+            Tile::Normal {
+                shape,
+                foreground,
+                background,
+            } => {
+                output.extend([b'B', c(shape)?, c(foreground)?, c(background)?]);
+            }
+            Tile::Special {
+                special,
+                background,
+            } => {
+                output.extend([b'C', c(special.into())?, c(background)?]);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decoded `T` line, a grid of [`MAP_WIDTH`] x [`MAP_HEIGHT`] tiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackMap {
+    tiles: Vec<Tile>,
+}
+
+impl Default for TrackMap {
+    fn default() -> Self {
+        Self {
+            tiles: vec![Tile::default(); MAP_WIDTH * MAP_HEIGHT],
+        }
+    }
+}
+
+impl TrackMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Tile> {
+        if x < MAP_WIDTH && y < MAP_HEIGHT {
+            self.tiles.get(y * MAP_WIDTH + x)
+        } else {
+            None
+        }
+    }
+
+    pub fn tile(&self, x: usize, y: usize) -> Tile {
+        self.tiles[y * MAP_WIDTH + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, tile: Tile) {
+        self.tiles[y * MAP_WIDTH + x] = tile;
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = (usize, usize, &Tile)> + '_ {
+        self.tiles
+            .iter()
+            .enumerate()
+            .map(|(i, tile)| (i % MAP_WIDTH, i / MAP_WIDTH, tile))
+    }
+
+    pub fn specials(&self) -> impl Iterator<Item = (usize, usize, Special)> + '_ {
+        self.tiles()
+            .filter_map(|(x, y, tile)| tile.special().map(|special| (x, y, special)))
+    }
+
+    pub fn decode(input: &str) -> anyhow::Result<Self> {
+        let expanded = expand(input)?;
+        let mut map = TrackMap::new();
+        let mut cursor = 0;
+        let mut next = |name: &str| -> anyhow::Result<u8> {
+            let c = *expanded
+                .get(cursor)
+                .with_context(|| format!("map ended while reading {}", name))?;
+            cursor += 1;
+            ALPHABET
+                .iter()
+                .position(|&a| a == c)
+                .map(|i| i as u8)
+                .with_context(|| format!("invalid map character {:?}", c as char))
+        };
+
+        for y in 0..MAP_HEIGHT {
+            for x in 0..MAP_WIDTH {
+                let tile = match next("tile")? {
+                    0 => Tile::Empty {
+                        shape: next("shape")?,
+                        foreground: next("foreground")?,
+                    },
+                    1 => Tile::Normal {
+                        shape: next("shape")?,
+                        foreground: next("foreground")?,
+                        background: next("background")?,
+                    },
+                    2 => Tile::Special {
+                        special: Special::from(next("special")?),
+                        background: next("background")?,
+                    },
+                    code => {
+                        let &(_, dx, dy) = COPY_OFFSETS
+                            .get(code as usize - 3)
+                            .with_context(|| format!("invalid tile code {}", code))?;
+                        if x < dx || y < dy {
+                            bail!("tile {},{} copies from outside the map", x, y);
+                        }
+                        map.tile(x - dx, y - dy)
+                    }
+                };
+                map.set(x, y, tile);
+            }
+        }
+
+        if cursor != expanded.len() {
+            bail!("{} trailing map characters", expanded.len() - cursor);
+        }
+        Ok(map)
+    }
+
+    pub fn encode(&self) -> anyhow::Result<String> {
+        let mut tokens = Vec::with_capacity(MAP_WIDTH * MAP_HEIGHT);
+        for (x, y, tile) in self.tiles() {
+            let copy = COPY_OFFSETS
+                .iter()
+                .find(|&&(_, dx, dy)| x >= dx && y >= dy && self.tile(x - dx, y - dy) == *tile);
+            match copy {
+                Some(&(code, _, _)) => tokens.push(code),
+                None => tile
+                    .encode(&mut tokens)
+                    .with_context(|| format!("cannot encode tile {},{}", x, y))?,
+            }
+        }
+        Ok(compress(&tokens))
+    }
+}
+
+/// Expands run lengths, `3D` is `DDD`.
+fn expand(input: &str) -> anyhow::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() * 2);
+    let mut count: Option<usize> = None;
+    for c in input.bytes() {
+        if c.is_ascii_digit() {
+            let digit = (c - b'0') as usize;
+            count = Some(count.unwrap_or(0) * 10 + digit);
+            if count > Some(MAX_EXPANDED_LEN) {
+                bail!("run length too long");
+            }
+            continue;
+        }
+        let count = count.take().unwrap_or(1);
+        if output.len() + count > MAX_EXPANDED_LEN {
+            bail!("map too long");
+        }
+        output.extend(std::iter::repeat_n(c, count));
+    }
+    if count.is_some() {
+        bail!("map ends with a run length");
+    }
+    Ok(output)
+}
+
+fn compress(input: &[u8]) -> String {
+    let mut output = String::with_capacity(input.len());
+    for run in input.chunk_by(|a, b| a == b) {
+        if run.len() > 1 {
+            output.push_str(&run.len().to_string());
+        }
+        output.push(run[0] as char);
+    }
+    output
+}
+
+impl FromStr for TrackMap {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TrackMap::decode(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, Element, Special, Tile, TrackMap, MAP_HEIGHT, MAP_WIDTH};
+    use crate::track::{tests::TEST_TRACK, Track};
+
+    #[test]
+    fn map_round_trip() {
+        let track: Track = TEST_TRACK.parse().unwrap();
+        let map = track.decode_map().unwrap();
+
+        assert_eq!(
+            map.tile(0, 0),
+            Tile::Normal {
+                shape: 0,
+                foreground: 16,
+                background: 16
+            }
+        );
+        assert_eq!(map.tile(0, 0).background(), Element::Block);
+        assert_eq!(map.tile(9, 1).special(), Some(Special::Start));
+        assert_eq!(
+            map.tile(1, 1).special(),
+            Some(Special::PlayerStart(Color::Blue))
+        );
+        assert_eq!(
+            map.specials()
+                .filter(|(_, _, s)| matches!(s, Special::TeleportStart(_)))
+                .count(),
+            4
+        );
+        assert_eq!(map.encode().unwrap(), track.map);
+    }
+
+    #[test]
+    fn map_encode_grass() {
+        let map = TrackMap::new();
+        let encoded = map.encode().unwrap();
+        let row = format!("E{}D", MAP_WIDTH - 1);
+        assert_eq!(
+            encoded,
+            format!("B3A{}D{}", MAP_WIDTH - 1, row.repeat(MAP_HEIGHT - 1))
+        );
+        assert_eq!(TrackMap::decode(&encoded).unwrap(), map);
+    }
+
+    #[test]
+    fn map_decode_errors() {
+        assert!(TrackMap::decode("BAAA").is_err());
+        assert!(TrackMap::decode("E").is_err());
+        assert!(TrackMap::decode("BAA!").is_err());
+        assert!(TrackMap::decode("BAAA99999999D").is_err());
+        assert!(TrackMap::decode(&format!("{}X", TrackMap::new().encode().unwrap())).is_err());
+    }
+
+    #[test]
+    fn map_encode_errors() {
+        let mut map = TrackMap::new();
+        map.set(
+            3,
+            4,
+            Tile::Normal {
+                shape: 52,
+                foreground: 0,
+                background: 0,
+            },
+        );
+        assert!(map.encode().is_err());
+        map.set(
+            3,
+            4,
+            Tile::Special {
+                special: Special::Other(60),
+                background: 0,
+            },
+        );
+        assert!(map.encode().is_err());
+        map.set(
+            3,
+            4,
+            Tile::Empty {
+                shape: 51,
+                foreground: 51,
+            },
+        );
+        assert!(map.encode().is_ok());
+    }
+
+    #[test]
+    fn special_codes() {
+        for code in 0..=52u8 {
+            assert_eq!(u8::from(Special::from(code)), code);
+            assert_eq!(u8::from(Element::from(code)), code);
+        }
+    }
+}
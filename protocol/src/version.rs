@@ -0,0 +1,68 @@
+use core::fmt;
+use std::fmt::Display;
+
+use crate::crypt::{GameCipher, CIPHER_CMDS};
+
+/// Protocol spoken by a client, negotiated from the `version` packet it sends
+/// right after `c new`.
+///
+/// Everything that differs between client versions is looked up from here, the
+/// cipher command table and later packet layouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProtocolVersion {
+    V35,
+}
+
+impl ProtocolVersion {
+    pub const SUPPORTED: [ProtocolVersion; 1] = [ProtocolVersion::V35];
+    pub const LATEST: ProtocolVersion = ProtocolVersion::V35;
+
+    /// `None` if the server cannot talk to clients sending `version`.
+    pub fn from_number(version: i32) -> Option<Self> {
+        ProtocolVersion::SUPPORTED
+            .into_iter()
+            .find(|supported| supported.number() == version)
+    }
+
+    pub fn number(&self) -> i32 {
+        match self {
+            ProtocolVersion::V35 => 35,
+        }
+    }
+
+    /// Strings the game cipher replaces with two byte codes.
+    pub fn cipher_commands(&self) -> &'static [&'static str] {
+        match self {
+            ProtocolVersion::V35 => &CIPHER_CMDS,
+        }
+    }
+
+    pub fn game_cipher(&self) -> GameCipher {
+        GameCipher::with_commands(self.cipher_commands())
+    }
+}
+
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.number())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProtocolVersion;
+
+    #[test]
+    fn negotiate_version() {
+        assert_eq!(ProtocolVersion::from_number(35), Some(ProtocolVersion::V35));
+        assert_eq!(ProtocolVersion::from_number(34), None);
+        assert_eq!(ProtocolVersion::LATEST.number(), 35);
+
+        let cipher = ProtocolVersion::V35.game_cipher();
+        assert_eq!(
+            cipher.encrypt("game\tbeginstroke\t70q4\n"),
+            "\u{1}\u{1}!70q4\n"
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use clap::Parser;
 use colored::Colorize;
 use protocol::client::ClientToServer;
-use protocol::common::Parse;
+use protocol::common::{Parse, Unknown};
 use protocol::server::ServerToClient;
 use std::net::{SocketAddr, ToSocketAddrs};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -33,6 +33,32 @@ fn print_diff_in_red(str1: &str, str2: &str) {
     println!(); // Ensure a newline at the end
 }
 
+fn log_parsed<T: Parse>(line: &str) -> Option<T> {
+    let (input, cmd) = match T::parse(line) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            println!("{} {}", "[ Parse error".red(), e);
+            return None;
+        }
+    };
+    if !input.is_empty() {
+        println!("[[{}]]", input.replace("\t", "\\t").replace("\n", "\\n"));
+    }
+
+    if cmd.as_string() != line {
+        print_diff_in_red(&cmd.as_string(), line);
+    }
+    Some(cmd)
+}
+
+fn log_unknown(unknown: &Unknown) {
+    let number = match unknown.packet_number {
+        Some(number) => number.to_string(),
+        None => "-".to_string(),
+    };
+    println!("{} {} {} ]", "[ Unknown".yellow(), number, unknown.tag);
+}
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
@@ -121,23 +147,11 @@ where
             );
             let i = i.to_owned() + "\n";
             if !client {
-                let (input, cmd) = <ServerToClient>::parse(&i).expect("fucked");
-                if !input.is_empty() {
-                    println!("[[{}]]", input.replace("\t", "\\t").replace("\n", "\\n"));
-                }
-
-                if cmd.as_string() != i {
-                    print_diff_in_red(&cmd.as_string(), &i);
-                }
-            } else {
-                let (input, cmd) = <ClientToServer>::parse(&i).expect("fucked");
-                if !input.is_empty() {
-                    println!("[[{}]]", input.replace("\t", "\\t").replace("\n", "\\n"));
-                }
-
-                if cmd.as_string() != i {
-                    print_diff_in_red(&cmd.as_string(), &i);
+                if let Some(ServerToClient::Unknown(unknown)) = log_parsed(&i) {
+                    log_unknown(&unknown);
                 }
+            } else if let Some(ClientToServer::Unknown(unknown)) = log_parsed(&i) {
+                log_unknown(&unknown);
             }
         }
 
@@ -0,0 +1,169 @@
+use std::{fs, net::SocketAddr, path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use protocol::{replay::Replay, server::ServerToClient};
+
+mod serve;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a replay as a timeline
+    Print {
+        /// Replay file written by the server
+        file: PathBuf,
+    },
+    /// Play a replay to every client that connects, with its original timing
+    Serve {
+        /// Replay file written by the server
+        file: PathBuf,
+
+        /// Address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:4242")]
+        listen: SocketAddr,
+
+        /// Playback speed, 2 plays twice as fast
+        #[arg(short, long, default_value_t = 1.0)]
+        speed: f64,
+
+        /// Encrypt like the real server instead of talking plaintext
+        #[arg(short, long)]
+        ciphers: bool,
+    },
+}
+
+fn load(file: &PathBuf) -> Result<Replay> {
+    fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?
+        .parse()
+        .with_context(|| format!("failed to parse {}", file.display()))
+}
+
+fn timestamp(at: Duration) -> String {
+    let millis = at.as_millis();
+    format!(
+        "{:02}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Player names by index, filled in from `game\tplayers` and `game\tjoin`.
+#[derive(Default)]
+struct Names(Vec<Option<String>>);
+
+impl Names {
+    fn set(&mut self, index: usize, name: &str) {
+        if self.0.len() <= index {
+            self.0.resize(index + 1, None);
+        }
+        self.0[index] = Some(name.to_string());
+    }
+
+    fn get(&self, index: usize) -> String {
+        match self.0.get(index) {
+            Some(Some(name)) => name.clone(),
+            _ => format!("#{}", index),
+        }
+    }
+}
+
+fn describe(packet: &ServerToClient, names: &mut Names) -> String {
+    match packet {
+        ServerToClient::GameGameInfo(info) => format!(
+            "game {}: {} tracks, {} players, {} strokes max",
+            info.name.0.as_deref().unwrap_or("-"),
+            info.num_tracks,
+            info.players,
+            info.max_strokes
+        ),
+        ServerToClient::GamePlayers(players) => {
+            let players = players.players.0.as_deref().unwrap_or_default();
+            for player in players {
+                names.set(player.index, &player.name);
+            }
+            let players: Vec<_> = players.iter().map(|player| player.name.as_str()).collect();
+            format!("players {}", players.join(", "))
+        }
+        ServerToClient::GameJoin(join) => {
+            names.set(join.index, &join.name);
+            format!("{} joins", join.name)
+        }
+        ServerToClient::GameStart(_) => "start".to_string(),
+        ServerToClient::GameStartTrack(track) => format!(
+            "track {} by {}, seed {}",
+            track.track.name, track.track.author, track.seed
+        ),
+        ServerToClient::GameStartTurn(turn) => format!("turn of {}", names.get(turn.index)),
+        ServerToClient::GameBeginStroke(stroke) => format!(
+            "{} strokes {},{} in mode {}",
+            names.get(stroke.index),
+            stroke.coords.x,
+            stroke.coords.y,
+            stroke.coords.mode
+        ),
+        ServerToClient::GameEndStroke(end) => {
+            let in_hole: Vec<_> = end
+                .in_hole
+                .iter()
+                .enumerate()
+                .filter(|(_, state)| state.is_in_hole())
+                .map(|(index, _)| names.get(index))
+                .collect();
+            match in_hole.is_empty() {
+                true => "nobody in the hole".to_string(),
+                false => format!("in the hole: {}", in_hole.join(", ")),
+            }
+        }
+        ServerToClient::GameChangeScore(scores) => {
+            let scores: Vec<_> = scores
+                .scores
+                .iter()
+                .enumerate()
+                .map(|(index, score)| match score {
+                    -1 => format!("{} -", names.get(index)),
+                    score => format!("{} {}", names.get(index), score),
+                })
+                .collect();
+            format!("track over: {}", scores.join(", "))
+        }
+        ServerToClient::GameSay(say) => format!("<{}> {}", names.get(say.index), say.message),
+        ServerToClient::GamePart(part) => format!("{} leaves", names.get(part.index)),
+        ServerToClient::GameEnd(end) => format!("game over, {:?}", end.winner),
+        packet => format!("{:?}", packet),
+    }
+}
+
+fn print(replay: &Replay) {
+    let mut names = Names::default();
+    for event in &replay.events {
+        println!(
+            "[{}] {}",
+            timestamp(event.at),
+            describe(&event.packet, &mut names)
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    match args.command {
+        Command::Print { file } => print(&load(&file)?),
+        Command::Serve {
+            file,
+            listen,
+            speed,
+            ciphers,
+        } => serve::run(load(&file)?, listen, speed, ciphers).await?,
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use protocol::{
+    client::ClientToServer,
+    codec::{Decoded, MinigolfCodec},
+    common::{PacketNumber, Parse, SomeAsTab},
+    crypt::{Ciphers, ConnCipher, GameCipher, CIPHER_MAGIC_DEFAULT},
+    replay::Replay,
+    server::{BasicInfo, Id, StatusGame, StatusLogin, VersOk},
+    version::ProtocolVersion,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::{sleep_until, Instant},
+};
+
+pub async fn run(replay: Replay, listen: SocketAddr, speed: f64, ciphers: bool) -> Result<()> {
+    if speed <= 0.0 {
+        bail!("speed must be positive");
+    }
+    let listener = TcpListener::bind(listen).await?;
+    println!("serving {} events on {}", replay.events.len(), listen);
+    let replay = Arc::new(replay);
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        println!("{} connected", addr);
+        let replay = replay.clone();
+        tokio::spawn(async move {
+            let connection = Connection::new(stream, ciphers);
+            match connection.play(&replay, speed).await {
+                Ok(()) => println!("{} watched the whole replay", addr),
+                Err(e) => println!("{} left: {}", addr, e),
+            }
+        });
+    }
+}
+
+/// One client watching the replay.
+struct Connection {
+    stream: TcpStream,
+    codec: MinigolfCodec,
+    ciphers: Ciphers,
+    use_ciphers: bool,
+    buffer: [u8; 512],
+    sent: u32,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, use_ciphers: bool) -> Self {
+        Self {
+            stream,
+            codec: MinigolfCodec::new(),
+            ciphers: Ciphers::none(),
+            use_ciphers,
+            buffer: [0; 512],
+            sent: 0,
+        }
+    }
+
+    fn next_num(&mut self) -> PacketNumber {
+        self.sent += 1;
+        PacketNumber(self.sent)
+    }
+
+    async fn write_str(&mut self, line: &str) -> Result<()> {
+        let line = self.ciphers.encrypt(line);
+        self.stream.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn write(&mut self, packet: impl Parse) -> Result<()> {
+        self.write_str(&packet.as_string()).await
+    }
+
+    async fn read(&mut self) -> Result<ClientToServer> {
+        loop {
+            match self.codec.next_packet()? {
+                Some(Decoded::Packet(packet)) => return Ok(packet),
+                Some(_) => continue,
+                None => {}
+            }
+            let read = self.stream.read(&mut self.buffer).await?;
+            if read == 0 {
+                bail!("disconnected");
+            }
+            self.codec.accept(&self.buffer[..read]);
+        }
+    }
+
+    fn set_ciphers(&mut self, ciphers: Ciphers) {
+        self.codec.set_ciphers(ciphers.clone());
+        self.ciphers = ciphers;
+    }
+
+    /// Logs the client in like the server does and sends it straight into the game.
+    async fn handshake(&mut self) -> Result<()> {
+        let seed = ConnCipher::get_random_seed();
+        self.write_str(&format!("h 1\nc io {}\nc crt 250\nc ctr\n", seed))
+            .await?;
+        if self.use_ciphers {
+            self.set_ciphers(Ciphers::new(
+                Some(GameCipher::new()),
+                Some(ConnCipher::new(CIPHER_MAGIC_DEFAULT, seed)),
+            ));
+        }
+        loop {
+            match self.read().await? {
+                ClientToServer::New(_) => self.write(Id { value: 1 }).await?,
+                ClientToServer::Version(version) => {
+                    let Some(version) = ProtocolVersion::from_number(version.version) else {
+                        bail!("unsupported protocol version {}", version.version);
+                    };
+                    if self.use_ciphers {
+                        let mut ciphers = self.ciphers.clone();
+                        ciphers.set_game_cipher(Some(version.game_cipher()));
+                        self.set_ciphers(ciphers);
+                    }
+                    self.write(VersOk {
+                        packet_number: PacketNumber(0),
+                    })
+                    .await?
+                }
+                ClientToServer::LoginType(_) => {
+                    let packet_number = self.next_num();
+                    self.write(StatusLogin {
+                        packet_number,
+                        status: SomeAsTab(None),
+                    })
+                    .await?
+                }
+                ClientToServer::TTLogin(_) => break,
+                _ => {}
+            }
+        }
+        let packet_number = self.next_num();
+        self.write(BasicInfo {
+            packet_number,
+            unconfirmed_email: false,
+            access_level: 0,
+            badword_filter: false,
+            guest_chat: true,
+        })
+        .await?;
+        let packet_number = self.next_num();
+        self.write(StatusGame { packet_number }).await
+    }
+
+    async fn play(mut self, replay: &Replay, speed: f64) -> Result<()> {
+        self.handshake().await?;
+        let start = Instant::now();
+        for event in &replay.events {
+            sleep_until(start + event.at.div_f64(speed)).await;
+            let packet_number = self.next_num();
+            self.write_str(&event.line(packet_number)).await?;
+        }
+        // Give the client a moment to show the end before hanging up
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Ok(())
+    }
+}
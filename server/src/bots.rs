@@ -0,0 +1,471 @@
+//! Players that live inside the server, filler opponents for testing and quiet
+//! hours.
+//!
+//! A [`Bot`] is a [`Client`](crate::clients::Client) like any other. Instead of a
+//! connection's worker it owns the other end of the `packets_to_send` and
+//! `received_packets` channels of its [`NewPlayer`], reads what the server sends
+//! and answers with client packets on every tick. Which strokes it plays is up to
+//! its [`StrokeStrategy`].
+
+use std::{collections::HashSet, f64::consts::TAU, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use flume::{Receiver, Sender};
+use protocol::{
+    client::{
+        ClientToServer, GameBack, GameBeginStroke, GameEndStroke, LobbyAccept, LobbyJmpt,
+        LobbySelectSelect, Pong,
+    },
+    common::{DLobbyType, PacketNumber, UserFlags},
+    mirror::{aim, nearest_hole, random_stroke, GameMirror},
+    physics::{Position, Simulation},
+    server::{Game, ServerToClient},
+    stroke::Stroke,
+};
+use rand::Rng;
+
+use crate::{clients::ClientId, initial_handler::NewPlayer, server::Server};
+
+/// Picks the strokes of a bot.
+pub trait StrokeStrategy {
+    /// The stroke of ball `index`. `simulation` is the track as it is now, `holes`
+    /// the centers of its holes.
+    fn choose(&mut self, simulation: &Simulation, holes: &[Position], index: usize) -> Stroke;
+}
+
+/// Shoots anywhere.
+pub struct RandomStrategy;
+
+impl StrokeStrategy for RandomStrategy {
+    fn choose(&mut self, _simulation: &Simulation, _holes: &[Position], _index: usize) -> Stroke {
+        random_stroke()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    /// Strokes tried before picking one.
+    fn candidates(self) -> usize {
+        match self {
+            Difficulty::Easy => 8,
+            Difficulty::Medium => 24,
+            Difficulty::Hard => 64,
+        }
+    }
+
+    /// How far the picked stroke may be off, in radians and as a share of its power.
+    fn sloppiness(self) -> (f64, f64) {
+        match self {
+            Difficulty::Easy => (0.15, 0.2),
+            Difficulty::Medium => (0.04, 0.05),
+            Difficulty::Hard => (0.0, 0.0),
+        }
+    }
+}
+
+/// Tries strokes on a copy of the track and plays the one that ends closest to a
+/// hole. The copy has the same random state, so a hard bot plays exactly what it
+/// tried while easier ones miss a bit.
+pub struct PhysicsStrategy {
+    pub difficulty: Difficulty,
+}
+
+impl PhysicsStrategy {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self { difficulty }
+    }
+}
+
+/// Distance from the ball to the point the stroke aims at, more is stronger.
+const MAX_AIM_DISTANCE: f64 = 200.0;
+
+fn distance_to_hole(position: Position, holes: &[Position]) -> f64 {
+    holes
+        .iter()
+        .map(|hole| (hole.x - position.x).hypot(hole.y - position.y))
+        .fold(f64::INFINITY, f64::min)
+}
+
+impl StrokeStrategy for PhysicsStrategy {
+    fn choose(&mut self, simulation: &Simulation, holes: &[Position], index: usize) -> Stroke {
+        let mut rng = rand::thread_rng();
+        let ball = simulation.ball(index).position;
+        let nearest = nearest_hole(holes, ball);
+
+        let mut best: Option<(f64, f64, f64)> = None;
+        for candidate in 0..self.difficulty.candidates() {
+            // Half of the strokes go roughly towards the hole, the rest anywhere
+            let angle = match nearest {
+                Some(hole) if candidate % 2 == 0 => {
+                    (hole.y - ball.y).atan2(hole.x - ball.x) + rng.gen_range(-0.2..0.2)
+                }
+                _ => rng.gen_range(0.0..TAU),
+            };
+            let distance = rng.gen_range(10.0..MAX_AIM_DISTANCE);
+            let mut trial = simulation.clone();
+            let outcome = trial.stroke(index, aim(ball, angle, distance));
+            let score = match outcome.in_hole {
+                true => 0.0,
+                false => distance_to_hole(outcome.position, holes),
+            };
+            if best.is_none_or(|(best, _, _)| score < best) {
+                best = Some((score, angle, distance));
+            }
+        }
+
+        let (_, angle, distance) = best.unwrap_or((0.0, 0.0, MAX_AIM_DISTANCE / 2.0));
+        let (angle_error, power_error) = self.difficulty.sloppiness();
+        let angle = angle + angle_error * rng.gen_range(-1.0..=1.0);
+        let distance = distance * (1.0 + power_error * rng.gen_range(-1.0..=1.0));
+        aim(ball, angle, distance)
+    }
+}
+
+impl FromStr for Box<dyn StrokeStrategy> {
+    type Err = anyhow::Error;
+
+    /// `random`, `easy`, `medium` or `hard`.
+    fn from_str(s: &str) -> Result<Self> {
+        let difficulty = match s {
+            "random" => return Ok(Box::new(RandomStrategy)),
+            "easy" => Difficulty::Easy,
+            "medium" => Difficulty::Medium,
+            "hard" => Difficulty::Hard,
+            _ => return Err(anyhow!("unknown bot strategy {:?}", s)),
+        };
+        Ok(Box::new(PhysicsStrategy::new(difficulty)))
+    }
+}
+
+/// One bot. It accepts every duo challenge, joins multiplayer games without a
+/// password that are waiting for players and goes back to the lobby when a game
+/// ends.
+pub struct Bot {
+    name: String,
+    /// What the server sends the bot.
+    inbox: Receiver<ServerToClient>,
+    /// What the bot sends, read by the server like a connection's packets.
+    outbox: Sender<ClientToServer>,
+    sent: u32,
+    strategy: Box<dyn StrokeStrategy>,
+    game: Option<GameMirror>,
+}
+
+impl Bot {
+    /// A bot and the player the server adds for it.
+    pub fn new(
+        network_id: usize,
+        name: &str,
+        strategy: Box<dyn StrokeStrategy>,
+    ) -> (Bot, NewPlayer) {
+        let (packets_to_send, inbox) = flume::unbounded();
+        let (outbox, received_packets) = flume::unbounded();
+        let bot = Bot {
+            name: name.to_string(),
+            inbox,
+            outbox,
+            sent: 0,
+            strategy,
+            game: None,
+        };
+        let player = NewPlayer {
+            network_id,
+            name: name.to_string(),
+            clan: None,
+            seed: 0,
+            language: "en".to_string(),
+            account_flags: UserFlags::WORM,
+            sent: 0,
+            received_packets,
+            packets_to_send,
+        };
+        (bot, player)
+    }
+
+    fn next_num(&mut self) -> PacketNumber {
+        self.sent += 1;
+        PacketNumber(self.sent)
+    }
+
+    fn send(&self, packet: ClientToServer) {
+        let _ = self.outbox.send(packet);
+    }
+
+    pub fn select_lobby(&mut self, lobby_type: DLobbyType) {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::LobbySelectSelect(LobbySelectSelect {
+            packet_number,
+            lobby_type,
+        }));
+    }
+
+    /// Joins multiplayer game `network_id`, the bot has to be in the multiplayer lobby.
+    pub fn join_game(&mut self, network_id: usize) {
+        let packet_number = self.next_num();
+        self.send(ClientToServer::LobbyJmpt(LobbyJmpt {
+            packet_number,
+            network_id,
+        }));
+    }
+
+    /// Whether the server dropped the bot's client.
+    pub fn is_disconnected(&self) -> bool {
+        self.inbox.is_disconnected() && self.inbox.is_empty()
+    }
+
+    /// Answers everything the server sent since the last tick. Games in `joining`
+    /// are skipped and the one the bot joins is added, so bots sharing the set
+    /// don't all rush the same free slot.
+    pub fn tick(&mut self, joining: &mut HashSet<usize>) {
+        while let Ok(packet) = self.inbox.try_recv() {
+            self.handle(packet, joining);
+        }
+    }
+
+    fn consider_game(&mut self, game: &Game, joining: &mut HashSet<usize>) {
+        if self.game.is_none()
+            && !game.passworded
+            && game.num_players < game.max_players
+            && joining.insert(game.id)
+        {
+            log::debug!("{} joins {}", self.name, game.name);
+            self.join_game(game.id);
+        }
+    }
+
+    fn handle(&mut self, packet: ServerToClient, joining: &mut HashSet<usize>) {
+        if let Some(game) = &mut self.game {
+            if let Some((index, stroke)) = game.handle(&packet) {
+                return self.end_stroke(index, stroke);
+            }
+        }
+        match packet {
+            ServerToClient::Ping(_) => self.send(ClientToServer::Pong(Pong {})),
+            ServerToClient::LobbyChallenge(challenge) => {
+                let packet_number = self.next_num();
+                self.send(ClientToServer::LobbyAccept(LobbyAccept {
+                    packet_number,
+                    challenger: challenge.challenger,
+                }));
+            }
+            ServerToClient::LobbyGamelistFull(list) => {
+                for game in list.games.iter().flatten() {
+                    self.consider_game(game, joining);
+                }
+            }
+            ServerToClient::LobbyGamelistAdd(add) => self.consider_game(&add.game, joining),
+            ServerToClient::LobbyGamelistChange(change) => {
+                self.consider_game(&change.game, joining)
+            }
+            ServerToClient::StatusGame(_) => self.game = Some(GameMirror::new()),
+            ServerToClient::StatusLobby(_) | ServerToClient::StatusLobbySelect(_) => {
+                self.game = None
+            }
+            ServerToClient::GameStartTurn(turn) => self.play_turn(turn.index),
+            ServerToClient::GameEnd(_) => {
+                let packet_number = self.next_num();
+                self.send(ClientToServer::GameBack(GameBack { packet_number }));
+            }
+            _ => {}
+        }
+    }
+
+    fn play_turn(&mut self, index: usize) {
+        let Some(game) = &self.game else {
+            return;
+        };
+        if game.index != Some(index) {
+            return;
+        }
+        let stroke = match game.simulation() {
+            Some(simulation) => self.strategy.choose(simulation, game.holes(), index),
+            // The track doesn't decode, there is nothing to aim at
+            None => random_stroke(),
+        };
+        let packet_number = self.next_num();
+        self.send(ClientToServer::GameBeginStroke(GameBeginStroke {
+            packet_number,
+            coords: stroke,
+        }));
+        self.end_stroke(index, stroke);
+    }
+
+    /// Plays the stroke of player `index` and tells the server where the balls ended.
+    fn end_stroke(&mut self, index: usize, stroke: Stroke) {
+        let Some(game) = &mut self.game else {
+            return;
+        };
+        let in_hole = game.stroke(index, stroke);
+        let packet_number = self.next_num();
+        self.send(ClientToServer::GameEndStroke(GameEndStroke {
+            packet_number,
+            index,
+            in_hole,
+        }));
+    }
+}
+
+/// Every bot of the server.
+#[derive(Default)]
+pub struct Bots {
+    bots: Vec<Bot>,
+}
+
+impl Bots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns one bot per lobby in the comma separated `BOTS` (`duo` or `multi`),
+    /// playing with `BOT_STRATEGY` (`random`, `easy`, `medium` or `hard`).
+    pub fn from_env(server: &mut Server) -> Result<Self> {
+        let mut bots = Self::new();
+        let strategy = std::env::var("BOT_STRATEGY").unwrap_or_else(|_| "medium".to_string());
+        let Ok(lobbies) = std::env::var("BOTS") else {
+            return Ok(bots);
+        };
+        for lobby in lobbies.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+            let lobby = match lobby {
+                "duo" => DLobbyType::Duo,
+                "multi" => DLobbyType::Multi,
+                _ => return Err(anyhow!("bots can't play in lobby {:?}", lobby)),
+            };
+            let name = format!("~bot-{}", bots.len() + 1);
+            let bot = bots.spawn(server, &name, strategy.parse()?);
+            bot.select_lobby(lobby);
+        }
+        log::info!("Spawned {} bots", bots.len());
+        Ok(bots)
+    }
+
+    /// Adds a bot to the server. It starts in the lobby selection.
+    pub fn spawn(
+        &mut self,
+        server: &mut Server,
+        name: &str,
+        strategy: Box<dyn StrokeStrategy>,
+    ) -> &mut Bot {
+        let (bot, player) = Bot::new(server.next_network_id(), name, strategy);
+        let id: ClientId = server.add_client(player);
+        log::debug!("bot {} is {:?}", name, id);
+        self.bots.push(bot);
+        self.bots.last_mut().unwrap()
+    }
+
+    pub fn tick(&mut self) {
+        self.bots.retain(|bot| !bot.is_disconnected());
+        let mut joining = HashSet::new();
+        for bot in &mut self.bots {
+            bot.tick(&mut joining);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use protocol::{
+        client::ClientToServer,
+        common::{PacketNumber, Parse, PlayerInfo, PlayerState},
+        physics::{Position, Simulation},
+        server::{GameStartTrack, ServerToClient},
+        track::{
+            map::{Element, Special, Tile, TrackMap},
+            Track,
+        },
+    };
+
+    use super::{Bot, Difficulty, PhysicsStrategy, StrokeStrategy};
+
+    fn map_with_hole() -> TrackMap {
+        let mut map = TrackMap::new();
+        map.set(
+            20,
+            12,
+            Tile::Special {
+                special: Special::Hole,
+                background: Element::Grass.into(),
+            },
+        );
+        map
+    }
+
+    #[test]
+    fn physics_gets_closer() {
+        let hole = Position::tile_center(20, 12);
+        let start = Position::tile_center(5, 12);
+        let mut simulation = Simulation::new(map_with_hole(), 99, 1);
+        simulation.set_position(0, start);
+
+        let mut strategy = PhysicsStrategy::new(Difficulty::Hard);
+        let stroke = strategy.choose(&simulation, &[hole], 0);
+        let outcome = simulation.stroke(0, stroke);
+        let before = (hole.x - start.x).hypot(hole.y - start.y);
+        let after = (hole.x - outcome.position.x).hypot(hole.y - outcome.position.y);
+        assert!(outcome.in_hole || after < before / 2.0, "{:?}", outcome);
+    }
+
+    #[test]
+    fn plays_its_turn() {
+        let (mut bot, player) = Bot::new(1, "~bot-1", "hard".parse().unwrap());
+        let track = Track {
+            name: "Test".to_string(),
+            map: map_with_hole().encode().unwrap(),
+            ..Default::default()
+        };
+        let server = player.packets_to_send;
+        let packets = [
+            ServerToClient::parse("d 1 status\tgame\n").unwrap().1,
+            ServerToClient::parse("d 2 game\towninfo\t1\t~bot-1\t-\n")
+                .unwrap()
+                .1,
+            ServerToClient::GameStartTrack(GameStartTrack {
+                packet_number: PacketNumber(3),
+                players: PlayerInfo(vec![PlayerState::InHole; 2]),
+                seed: 5,
+                track,
+            }),
+            ServerToClient::parse("d 4 game\tbeginstroke\t0\t70q4\n")
+                .unwrap()
+                .1,
+            ServerToClient::parse("d 5 game\tstartturn\t1\n").unwrap().1,
+            ServerToClient::parse("c ping\n").unwrap().1,
+            ServerToClient::parse("d 6 lobby\tchallenge\tsomeone\t1\t0\t20\t60\t0\t1\t0\t0\n")
+                .unwrap()
+                .1,
+        ];
+        for packet in packets {
+            assert!(
+                !matches!(packet, ServerToClient::Unknown(_)),
+                "{:?}",
+                packet
+            );
+            server.send(packet).unwrap();
+        }
+        bot.tick(&mut HashSet::new());
+
+        let sent: Vec<_> = player.received_packets.try_iter().collect();
+        assert!(matches!(
+            &sent[0],
+            ClientToServer::GameEndStroke(end) if end.index == 0 && end.in_hole.len() == 2
+        ));
+        assert!(matches!(sent[1], ClientToServer::GameBeginStroke(_)));
+        assert!(matches!(&sent[2], ClientToServer::GameEndStroke(end) if end.index == 1));
+        assert!(matches!(sent[3], ClientToServer::Pong(_)));
+        assert!(matches!(
+            &sent[4],
+            ClientToServer::LobbyAccept(accept) if accept.challenger == "someone"
+        ));
+    }
+}
@@ -7,12 +7,12 @@ use std::{
 use flume::{Receiver, Sender};
 use protocol::{
     client::ClientToServer,
-    common::{DLobbyType, NonEmptyOption, PacketNumber, User},
+    common::{DLobbyType, NonEmptyOption, PacketNumber, User, UserFlags},
     server::{LobbyNumberOfUsers, Ping, ServerToClient},
 };
 use slab::Slab;
 
-use crate::{game::GameId, initial_handler::NewPlayer};
+use crate::{game::GameId, initial_handler::NewPlayer, state::ConnectionState};
 
 #[derive(Default)]
 pub struct Clients {
@@ -28,12 +28,13 @@ pub struct Client {
     received_packets: Receiver<ClientToServer>,
     name: String,
     clan: Option<String>,
-    lobby: RefCell<Option<DLobbyType>>,
-    game: RefCell<Option<GameId>>,
+    state: Cell<ConnectionState>,
     language: String,
     network_id: NetworkId,
     seed: i32,
+    account_flags: UserFlags,
     no_challenges: Cell<bool>,
+    violations: Cell<u32>,
     sent: RefCell<AtomicU32>,
     last_pong: RefCell<Instant>,
     disconnected: Cell<bool>,
@@ -49,12 +50,13 @@ impl Client {
             clan: player.clan,
             language: player.language,
             id: None,
-            lobby: RefCell::new(None),
-            game: RefCell::new(None),
+            state: Cell::new(ConnectionState::LobbySelect),
             network_id: NetworkId(player.network_id),
             seed: player.seed,
+            account_flags: player.account_flags,
             disconnected: Cell::new(false),
             no_challenges: Cell::new(false),
+            violations: Cell::new(0),
             last_pong: RefCell::new(Instant::now()),
             sent: RefCell::new(AtomicU32::new(player.sent)), // initial handling
         }
@@ -84,12 +86,23 @@ impl Client {
         self.no_challenges.get()
     }
 
+    pub fn network_id(&self) -> NetworkId {
+        self.network_id
+    }
+
     pub fn id(&self) -> Option<ClientId> {
         self.id
     }
 
-    pub fn lobby_select(&self) -> bool {
-        self.game().is_none() && self.lobby().is_none()
+    pub fn state(&self) -> ConnectionState {
+        self.state.get()
+    }
+
+    /// Counts a packet that is illegal in the current state, returns the total.
+    pub fn add_violation(&self) -> u32 {
+        let violations = self.violations.get() + 1;
+        self.violations.set(violations);
+        violations
     }
 
     pub fn set_client_id(&mut self, client_id: ClientId) {
@@ -97,10 +110,10 @@ impl Client {
     }
 
     pub fn game(&self) -> Option<GameId> {
-        *self.game.borrow()
+        self.state().game()
     }
     pub fn lobby(&self) -> Option<DLobbyType> {
-        *self.lobby.borrow()
+        self.state().lobby()
     }
     pub fn send_packet(&self, packet: ServerToClient) {
         let _ = self.packets_to_send.try_send(packet);
@@ -110,7 +123,10 @@ impl Client {
     }
 
     pub fn set_lobby(&self, lobby: Option<DLobbyType>) {
-        *self.lobby.borrow_mut() = lobby;
+        self.state.set(match lobby {
+            Some(lobby) => ConnectionState::Lobby(lobby),
+            None => ConnectionState::LobbySelect,
+        });
     }
     pub fn set_pong(&self) {
         *self.last_pong.borrow_mut() = Instant::now();
@@ -127,27 +143,25 @@ impl Client {
         &self.language
     }
 
+    /// Enters or leaves a game of the current lobby.
     pub fn set_game(&self, game: Option<GameId>) {
-        *self.game.borrow_mut() = game;
+        let Some(lobby) = self.lobby() else {
+            log::error!("{} joined game {:?} outside of a lobby", self.name(), game);
+            return;
+        };
+        self.state.set(match game {
+            Some(game) => ConnectionState::Game { lobby, game },
+            None => ConnectionState::Lobby(lobby),
+        });
     }
 
     pub fn clan(&self) -> Option<&String> {
         self.clan.as_ref()
     }
-    pub fn status_string(&self) -> String {
-        //w worm r registered v vip s sherif n no challenges
-        let mut s = String::new();
-
-        if let Some('~') = self.name().chars().next() {
-            s.push('w');
-        } else {
-            s.push('r');
-        }
-
-        if self.no_challenges() {
-            s.push('n');
-        }
-        s
+    pub fn flags(&self) -> UserFlags {
+        let mut flags = self.account_flags;
+        flags.set(UserFlags::NO_CHALLENGES, self.no_challenges());
+        flags
     }
 }
 
@@ -261,7 +275,7 @@ impl From<&Client> for User {
     fn from(val: &Client) -> Self {
         User {
             id_username: format!("3:{}", val.name()),
-            value_1: val.status_string(),
+            flags: val.flags(),
             rank: 999,
             lang: val.language.to_string(),
             value_2: NonEmptyOption(None),
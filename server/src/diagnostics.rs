@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use protocol::{
+    client::TLog,
+    common::{Parse, PlayerInfo},
+};
+
+use crate::clients::NetworkId;
+
+/// Client logs kept before the oldest ones are dropped.
+pub const MAX_CLIENT_LOGS: usize = 1000;
+/// Desyncs kept before the oldest ones are dropped.
+pub const MAX_DESYNCS: usize = 1000;
+
+/// A `s tlog` sent by a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientLog {
+    pub network_id: NetworkId,
+    pub received: SystemTime,
+    pub count: i32,
+    pub id: String,
+    pub lines: Vec<String>,
+}
+
+impl ClientLog {
+    pub fn message(&self) -> String {
+        self.lines.join("\n")
+    }
+}
+
+/// A client whose `game\tendstroke` disagreed with the server's simulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Desync {
+    pub network_id: NetworkId,
+    pub received: SystemTime,
+    /// Network id of the game.
+    pub game: usize,
+    pub track: String,
+    /// Index of the player whose stroke it was.
+    pub player: usize,
+    pub expected: PlayerInfo,
+    pub reported: PlayerInfo,
+}
+
+/// Logs clients reported and desyncs the server noticed, shared by the connection
+/// workers and the game loop.
+#[derive(Clone, Default)]
+pub struct Diagnostics {
+    logs: Arc<Mutex<VecDeque<ClientLog>>>,
+    desyncs: Arc<Mutex<VecDeque<Desync>>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, network_id: NetworkId, log: TLog) {
+        log::info!(
+            "client log {} from {:?}: {} {:?}",
+            log.count,
+            network_id,
+            log.id,
+            log.log
+        );
+        let mut logs = self.logs.lock().unwrap();
+        if logs.len() == MAX_CLIENT_LOGS {
+            logs.pop_front();
+        }
+        logs.push_back(ClientLog {
+            network_id,
+            received: SystemTime::now(),
+            count: log.count,
+            id: log.id,
+            lines: log.log,
+        });
+    }
+
+    /// Logs of one connection, oldest first.
+    pub fn for_network_id(&self, network_id: NetworkId) -> Vec<ClientLog> {
+        self.query(|log| log.network_id == network_id)
+    }
+
+    /// Logs received at or after `time`, oldest first.
+    pub fn since(&self, time: SystemTime) -> Vec<ClientLog> {
+        self.query(|log| log.received >= time)
+    }
+
+    pub fn query(&self, filter: impl Fn(&ClientLog) -> bool) -> Vec<ClientLog> {
+        let logs = self.logs.lock().unwrap();
+        logs.iter().filter(|log| filter(log)).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.logs.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn record_desync(&self, desync: Desync) {
+        log::warn!(
+            "{:?} is out of sync on {:?} in game {}: stroke of {} ended {} but the server has {}",
+            desync.network_id,
+            desync.track,
+            desync.game,
+            desync.player,
+            desync.reported.as_string(),
+            desync.expected.as_string()
+        );
+        let mut desyncs = self.desyncs.lock().unwrap();
+        if desyncs.len() == MAX_DESYNCS {
+            desyncs.pop_front();
+        }
+        desyncs.push_back(desync);
+    }
+
+    /// Desyncs of one connection, oldest first.
+    pub fn desyncs_for(&self, network_id: NetworkId) -> Vec<Desync> {
+        let desyncs = self.desyncs.lock().unwrap();
+        desyncs
+            .iter()
+            .filter(|desync| desync.network_id == network_id)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use protocol::{
+        client::TLog,
+        common::{Parse, PlayerInfo, PlayerState},
+    };
+
+    use super::{Desync, Diagnostics, MAX_CLIENT_LOGS};
+    use crate::clients::NetworkId;
+
+    fn tlog(count: i32) -> TLog {
+        TLog::parse(&format!("s tlog\t{}\terror\tfirst\tsecond\n", count))
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn record_and_query() {
+        let diagnostics = Diagnostics::new();
+        let start = SystemTime::now();
+        diagnostics.record(NetworkId(1), tlog(1));
+        diagnostics.clone().record(NetworkId(2), tlog(1));
+        diagnostics.record(NetworkId(1), tlog(2));
+
+        let logs = diagnostics.for_network_id(NetworkId(1));
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[1].count, 2);
+        assert_eq!(logs[0].message(), "first\nsecond");
+        assert_eq!(diagnostics.since(start).len(), 3);
+        assert_eq!(diagnostics.query(|log| log.id == "error").len(), 3);
+
+        for count in 0..MAX_CLIENT_LOGS as i32 {
+            diagnostics.record(NetworkId(3), tlog(count));
+        }
+        assert_eq!(diagnostics.len(), MAX_CLIENT_LOGS);
+        assert!(diagnostics.for_network_id(NetworkId(1)).is_empty());
+    }
+
+    #[test]
+    fn record_desyncs() {
+        let diagnostics = Diagnostics::new();
+        let desync = Desync {
+            network_id: NetworkId(4),
+            received: SystemTime::now(),
+            game: 1,
+            track: "Test".to_string(),
+            player: 0,
+            expected: PlayerInfo(vec![PlayerState::InHole, PlayerState::Left]),
+            reported: PlayerInfo(vec![PlayerState::NotInHole, PlayerState::Left]),
+        };
+        diagnostics.record_desync(desync.clone());
+        assert_eq!(diagnostics.desyncs_for(NetworkId(4)), vec![desync]);
+        assert!(diagnostics.desyncs_for(NetworkId(1)).is_empty());
+    }
+}
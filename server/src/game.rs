@@ -1,28 +1,42 @@
 use anyhow::{bail, Result};
 use protocol::{
-    client::{self, LobbyChallenge, LobbyCmpt, LobbyCspt},
-    common::{Collision, DLobbyType, NonEmptyOption, Scoring, TrackType, WaterEvent, WeightEnd},
+    client::{LobbyChallenge, LobbyCmpt, LobbyCspt},
+    common::{
+        Collision, DLobbyType, NonEmptyOption, PacketNumber, PlayerInfo, PlayerState, Scoring,
+        SomeAsTab, TrackType, WaterEvent, WeightEnd,
+    },
+    physics::Simulation,
+    replay::Replay,
     server::{
-        Game, GameEnd, GameGameInfo, GameResetVoteSkip, GameStart, GameStartTrack, GameStartTurn,
-        LobbyGamelistRemove, ServerToClient,
+        Game, GameChangeScore, GameEnd, GameEndStroke, GameGameInfo, GamePlayers,
+        GameResetVoteSkip, GameStart, GameStartTrack, GameStartTurn, LobbyGamelistRemove, Player,
+        ServerToClient,
     },
+    stroke::Stroke,
+    track::Track,
 };
+use rand::Rng;
 use slab::Slab;
 use std::{
     borrow::{Borrow, BorrowMut},
     cell::{Cell, RefCell, RefMut},
     collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
     sync::atomic::AtomicUsize,
+    time::SystemTime,
 };
 use std::{ops::Add, time::Instant};
 
 use crate::{
     clients::{Client, ClientId},
     server::Server,
+    tracks::TrackLibrary,
 };
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-pub struct GameId(usize);
+pub struct GameId(pub usize);
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
 pub enum GameStatus {
@@ -53,6 +67,19 @@ pub struct MinigolfGame {
     network_id: usize,
     turn_start: Cell<Instant>,
     players: RefCell<Vec<Option<GamePlayer>>>,
+    tracks: Vec<Rc<Track>>,
+    /// Seed of the current track, clients derive its randomness from it.
+    seed: Cell<i32>,
+    /// Balls of the current track, `None` if its map doesn't decode.
+    simulation: RefCell<Option<Simulation>>,
+    /// Player index of the last stroke, until every client has ended it.
+    stroke: Cell<Option<usize>>,
+    /// Who the simulation puts in the hole after the last stroke. Clients decide,
+    /// this is only compared with what they report.
+    simulated: RefCell<Option<PlayerInfo>>,
+    /// When the game started, replay events are timed from here.
+    started: Cell<Instant>,
+    replay: RefCell<Replay>,
 }
 
 #[derive(Debug)]
@@ -96,6 +123,16 @@ impl MinigolfGame {
     }
 
     pub fn next_track(&self, server: &Server) {
+        let scores = self
+            .players()
+            .iter()
+            .map(|player| player.as_ref().and_then(|p| p.strokes.last().copied()))
+            .map(|strokes| strokes.unwrap_or(-1))
+            .collect();
+        self.record(ServerToClient::GameChangeScore(GameChangeScore {
+            packet_number: PacketNumber(0),
+            scores,
+        }));
         let cur_track = self.cur_track.get().add(1);
         for game_player in self.players_mut().iter_mut() {
             if let Some(player) = game_player {
@@ -111,6 +148,10 @@ impl MinigolfGame {
                             packet_number: client.next_num(),
                             winner: vec![index as i32], //TODO
                         }));
+                        self.record(ServerToClient::GameEnd(GameEnd {
+                            packet_number: PacketNumber(0),
+                            winner: vec![index as i32],
+                        }));
                         self.status.set(GameStatus::Ended);
                         return;
                     }
@@ -118,12 +159,17 @@ impl MinigolfGame {
             }
         }
         if cur_track > self.num_tracks {
+            let winner = self.winners();
+            self.record(ServerToClient::GameEnd(GameEnd {
+                packet_number: PacketNumber(0),
+                winner: winner.clone(),
+            }));
             for game_player in self.players().iter() {
                 if let Some(game_player) = game_player {
                     if let Some(client) = server.clients.get(game_player.id) {
                         client.send_packet(ServerToClient::GameEnd(GameEnd {
                             packet_number: client.next_num(),
-                            winner: vec![1], //TODO
+                            winner: winner.clone(),
                         }));
                         self.status.set(GameStatus::Ended);
                     }
@@ -132,12 +178,17 @@ impl MinigolfGame {
             return;
         }
         self.cur_track.set(cur_track);
+        self.begin_track();
 
         let turn = self.get_next_turn();
         if turn.is_none() {
             log::error!("failed to get next turn in next track\n");
             panic!();
         }
+        self.record(ServerToClient::GameStartTurn(GameStartTurn {
+            packet_number: PacketNumber(0),
+            index: turn.unwrap(),
+        }));
 
         for game_player in self.players().iter() {
             if let Some(game_player) = game_player {
@@ -155,13 +206,180 @@ impl MinigolfGame {
         }
     }
 
+    /// Picks the seed of the track that was just switched to and puts the balls on it.
+    fn begin_track(&self) {
+        let seed = rand::thread_rng().gen();
+        self.seed.set(seed);
+        self.stroke.set(None);
+        *self.simulated.borrow_mut() = None;
+        let simulation = self.current_track().and_then(|track| {
+            Simulation::from_track(track, seed, self.max_players)
+                .inspect_err(|e| log::error!("cannot simulate {:?}: {}", track.name, e))
+                .ok()
+        });
+        *self.simulation.borrow_mut() = simulation.map(|mut simulation| {
+            simulation.set_collisions(self.collision == Collision::Yes);
+            simulation
+        });
+        self.record(ServerToClient::GameStartTrack(GameStartTrack {
+            packet_number: PacketNumber(0),
+            players: self.start_track_players(),
+            seed,
+            track: self.current_track().cloned().unwrap_or_default(),
+        }));
+    }
+
+    /// Starts the stroke of player `index` and simulates it to compare with the
+    /// clients later. Returns `None` if the track can't be simulated.
+    pub fn stroke(&self, index: usize, stroke: Stroke) -> Option<PlayerInfo> {
+        self.stroke.set(Some(index));
+        let mut simulation = self.simulation.borrow_mut();
+        let simulation = simulation.as_mut()?;
+        let outcome = simulation.stroke(index, stroke);
+        log::debug!(
+            "stroke of {} ends at {:.1},{:.1} after {} frames",
+            index,
+            outcome.position.x,
+            outcome.position.y,
+            outcome.frames
+        );
+        let in_hole: PlayerInfo = self
+            .players()
+            .iter()
+            .zip(simulation.balls())
+            .map(|(player, ball)| match player {
+                Some(_) => ball.in_hole.into(),
+                None => PlayerState::Left,
+            })
+            .collect();
+        *self.simulated.borrow_mut() = Some(in_hole.clone());
+        Some(in_hole)
+    }
+
+    /// The player index and simulated in hole state of the last stroke, if the
+    /// server simulated it and `reported` disagrees.
+    pub fn desync(&self, reported: &PlayerInfo) -> Option<(usize, PlayerInfo)> {
+        let simulated = self.simulated.borrow().clone()?;
+        let player = self.stroke.get()?;
+        disagrees(&simulated, reported).then_some((player, simulated))
+    }
+
+    /// The player index of the last stroke, once.
+    pub fn take_stroke(&self) -> Option<usize> {
+        self.stroke.take()
+    }
+
+    /// Who the clients have reported in the hole.
+    pub fn in_hole(&self) -> PlayerInfo {
+        self.players()
+            .iter()
+            .map(|player| match player {
+                Some(player) => player.in_hole.into(),
+                None => PlayerState::Left,
+            })
+            .collect()
+    }
+
+    pub fn seed(&self) -> i32 {
+        self.seed.get()
+    }
+
+    /// Adds `packet` to the replay, numbered `d 0`. Replays begin when the game
+    /// starts, anything before is dropped.
+    pub fn record(&self, packet: ServerToClient) {
+        if self.status() == GameStatus::WaitingPlayers {
+            return;
+        }
+        let at = self.started.get().elapsed();
+        self.replay.borrow_mut().push(at, packet);
+    }
+
+    /// Writes the replay into `dir` on a blocking thread, so the tick loop doesn't
+    /// wait for the disk.
+    pub fn save_replay(&self, dir: &Path) {
+        let unix = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let dir = dir.to_path_buf();
+        let path = dir.join(format!("game-{}-{}.replay", self.network_id, unix));
+        let name = self.name();
+        let replay = self.replay.borrow().to_string();
+        tokio::task::spawn_blocking(move || {
+            match fs::create_dir_all(&dir).and_then(|()| fs::write(&path, replay)) {
+                Ok(()) => log::info!("saved replay of {} to {}", name, path.display()),
+                Err(e) => log::error!("cannot save replay of {}: {}", name, e),
+            }
+        });
+    }
+
+    /// `game\tend` flags of every player, 1 for the fewest strokes over all tracks
+    /// and -1 for everyone else. Tracks a player gave up on count after any number
+    /// of strokes, players who left never win.
+    fn winners(&self) -> Vec<i32> {
+        let scores: Vec<_> = self
+            .players()
+            .iter()
+            .map(|player| {
+                player.as_ref().map(|player| {
+                    let given_up = player.strokes.iter().filter(|&&s| s < 0).count();
+                    let strokes: i64 = player
+                        .strokes
+                        .iter()
+                        .filter(|&&s| s >= 0)
+                        .map(|&s| s as i64)
+                        .sum();
+                    (given_up, strokes)
+                })
+            })
+            .collect();
+        let best = scores.iter().flatten().min().copied();
+        scores
+            .iter()
+            .map(|score| {
+                if score.is_some() && *score == best {
+                    1
+                } else {
+                    -1
+                }
+            })
+            .collect()
+    }
+
     pub fn is_solo(&self) -> bool {
         self.game_type == DLobbyType::Solo || self.game_type() == DLobbyType::SoloIncognito
     }
 
     pub fn start(&self, server: &Server) {
         self.status.set(GameStatus::InGame);
+        self.started.set(Instant::now());
+        self.record(ServerToClient::GameGameInfo(GameGameInfo::from(self)));
+        let players = self
+            .players()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, player)| {
+                let client = server.clients.get(player.as_ref()?.id)?;
+                Some(Player {
+                    index,
+                    name: client.name().to_string(),
+                    clan: NonEmptyOption(client.clan().cloned()),
+                })
+            })
+            .collect();
+        self.record(ServerToClient::GamePlayers(GamePlayers {
+            packet_number: PacketNumber(0),
+            players: SomeAsTab(Some(players)),
+        }));
+        self.record(ServerToClient::GameStart(GameStart {
+            packet_number: PacketNumber(0),
+        }));
         self.cur_track.set(self.cur_track.get().add(1));
+        self.begin_track();
+        self.record(ServerToClient::GameStartTurn(GameStartTurn {
+            packet_number: PacketNumber(0),
+            index: self.turn.get(),
+        }));
 
         for game_player in self.players().iter() {
             if let Some(game_player) = game_player {
@@ -200,15 +418,11 @@ impl MinigolfGame {
         None // No valid turn found
     }
 
-    pub fn get_start_track_players_string(&self) -> String {
-        let mut status_string = String::new();
-
-        for player in self.players().iter() {
-            let status = if player.is_some() { 't' } else { 'f' };
-            status_string.push(status);
-        }
-
-        status_string
+    pub fn start_track_players(&self) -> PlayerInfo {
+        self.players()
+            .iter()
+            .map(|player| PlayerState::from(player.is_some()))
+            .collect()
     }
 
     pub fn players(&self) -> std::cell::Ref<'_, Vec<Option<GamePlayer>>> {
@@ -279,18 +493,28 @@ impl MinigolfGame {
     pub fn set_turn_start(&self, turn_start: Instant) {
         self.turn_start.set(turn_start);
     }
+
+    pub fn current_track(&self) -> Option<&Track> {
+        let index = self.cur_track.get().checked_sub(1)?;
+        self.tracks.get(index).map(|track| track.as_ref())
+    }
 }
 
 pub struct GameServer {
     game_rooms: Slab<MinigolfGame>,
     next_network_id: AtomicUsize,
+    tracks: TrackLibrary,
+    /// Where replays of finished games are written.
+    replays_dir: PathBuf,
 }
 
 impl GameServer {
-    pub fn new() -> Self {
+    pub fn new(tracks: TrackLibrary, replays_dir: impl Into<PathBuf>) -> Self {
         Self {
             game_rooms: Slab::new(),
             next_network_id: AtomicUsize::new(1),
+            tracks,
+            replays_dir: replays_dir.into(),
         }
     }
     fn next_network_id(&self) -> usize {
@@ -336,6 +560,13 @@ impl GameServer {
             cur_track: Cell::new(0),
             turn_start: Cell::new(Instant::now()),
             players: RefCell::new(Vec::new()),
+            tracks: self.tracks.pick(packet.track_types, packet.num_tracks),
+            seed: Cell::new(0),
+            simulation: RefCell::new(None),
+            stroke: Cell::new(None),
+            simulated: RefCell::new(None),
+            started: Cell::new(Instant::now()),
+            replay: RefCell::new(Replay::new()),
         };
         let _ = game.add_player(client.id().unwrap());
         /*server.broadcast_lobby_with(Some(game.game_type), |c| {
@@ -365,6 +596,13 @@ impl GameServer {
             cur_track: Cell::new(0),
             turn_start: Cell::new(Instant::now()),
             players: RefCell::new(Vec::new()),
+            tracks: self.tracks.pick(packet.track_types, packet.num_tracks),
+            seed: Cell::new(0),
+            simulation: RefCell::new(None),
+            stroke: Cell::new(None),
+            simulated: RefCell::new(None),
+            started: Cell::new(Instant::now()),
+            replay: RefCell::new(Replay::new()),
         };
         self.add_game(game)
     }
@@ -412,6 +650,13 @@ impl GameServer {
             network_id: self.next_network_id(),
             cur_track: Cell::new(0),
             turn_start: Cell::new(Instant::now()),
+            tracks: self.tracks.pick(packet.track_type, packet.num_tracks),
+            seed: Cell::new(0),
+            simulation: RefCell::new(None),
+            stroke: Cell::new(None),
+            simulated: RefCell::new(None),
+            started: Cell::new(Instant::now()),
+            replay: RefCell::new(Replay::new()),
         };
 
         let _ = game.add_player(client.id().unwrap());
@@ -446,6 +691,21 @@ impl GameServer {
                 continue; //dont fuck with removed rooms anymore
             }
             if room.all_end_strokes() {
+                if let Some(index) = room.take_stroke() {
+                    let in_hole = room.in_hole();
+                    server.broadcast_game_with(room, |c| {
+                        c.send_packet(ServerToClient::GameEndStroke(GameEndStroke {
+                            packet_number: c.next_num(),
+                            index,
+                            in_hole: in_hole.clone(),
+                        }))
+                    });
+                    room.record(ServerToClient::GameEndStroke(GameEndStroke {
+                        packet_number: PacketNumber(0),
+                        index,
+                        in_hole,
+                    }));
+                }
                 //TODO handle scoring
                 if let Some(turn) = room.get_next_turn() {
                     server.broadcast_game_with(room, |c| {
@@ -454,6 +714,10 @@ impl GameServer {
                             index: turn,
                         }))
                     });
+                    room.record(ServerToClient::GameStartTurn(GameStartTurn {
+                        packet_number: PacketNumber(0),
+                        index: turn,
+                    }));
                 } else {
                     for (i, c) in room.players_mut().iter_mut().enumerate() {
                         if let Some(c) = c {
@@ -501,7 +765,10 @@ impl GameServer {
         }
 
         for id in rooms_to_remove {
-            self.game_rooms.remove(id);
+            let room = self.game_rooms.remove(id);
+            if room.status() != GameStatus::WaitingPlayers {
+                room.save_replay(&self.replays_dir);
+            }
         }
     }
 
@@ -559,48 +826,40 @@ impl From<&MinigolfGame> for GameGameInfo {
     }
 }
 
-impl From<&client::LobbyChallenge> for MinigolfGame {
-    fn from(value: &client::LobbyChallenge) -> Self {
-        MinigolfGame {
-            game_type: DLobbyType::Duo,
-            name: Some(value.challenged.clone()),
-            password: None,
-            permission: 0,
-            max_players: 2,
-            turn: Cell::new(0),
-            num_tracks: value.num_tracks,
-            track_type: value.track_types,
-            max_strokes: value.max_strokes,
-            time_limit: value.time_limit,
-            water_event: value.water_event,
-            collision: value.collision,
-            track_scoring: value.track_scoring,
-            track_scoring_weighted_end: value.track_scoring_weighted_end,
-            status: Cell::new(GameStatus::WaitingPlayers),
-            network_id: 1337,
-            players: RefCell::new(Vec::new()),
-            cur_track: Cell::new(0),
-            turn_start: Cell::new(Instant::now()),
-        }
-    }
+/// Compares only the slots the server simulated a player in. What clients send
+/// for empty slots isn't known.
+fn disagrees(simulated: &PlayerInfo, reported: &PlayerInfo) -> bool {
+    simulated
+        .iter()
+        .zip(reported.iter())
+        .any(|(simulated, reported)| simulated != PlayerState::Left && simulated != reported)
 }
 
 pub fn track(client: &Client, game: &MinigolfGame) -> GameStartTrack {
     GameStartTrack {
         packet_number: client.next_num(),
-        players: game.get_start_track_players_string(),
-        seed: 0,
-        trackstrings: vec![
-                "V 1".to_string(),
-                "A Nokkis".to_string(),//
-                "N Test".to_string(),//
-                "T BA2Q47DCUAECYABA2VCZAGCaAGCbAGC2AB3A36DCBAFEBCWABA2W5GEB3A38D2EB3A46D2EBA2DBABDBACDE40DBWQABA2Q2D5E17DCWI3DE8DCXTDE9DCOA6E14DCWI2DBAMABANABAOABAPAE6DCWTDF2E7D2H2D5E14DBAIABAKAGI10DEG5DC2DBA2NBATDE3DCMA6E11DCE3D4E17DCDCBAMN2ED2H2D5E14D4E17DCD2BAON2E3DCKA6E16D2E17DCDABAPN2ED2H2D5E14DBAKA2DE3DBQAT4DE15DCIA6E20DBIATBA2Q4DCDABJATE11DBPAQH2D5E19DBU2ACDABAGQ3DBAHQBAIQBA2QBRATE12DCJA6E19DBTATBA2QBAFQDBASQD5E10D2H2D5E19D2EBAEQBASQBbASBYASF4E12DCLA6E19D4EB3AD5E10D2H2D5E19D4EBVASD5E12DCNA6E5DCG3DBUASE9D4EHD5E10D2H2D5E19D4EBaASBZAS5E12DCPA6E13DBWMAE4D3EBALQFDBAJQD3E10D2H2D5E13D2E4D4EBAKQ3DCDABU2AE7DB2AQ2DFGD6E13D2E5DBLATCDAI4DBKATB3A4DB2AQE8DECDA2E2CADE12D2E6DBU2ABSAT4DB3AB2AQ4DF3DCT2DCSACQPDCRAECVAFI29DBAR4DBA2Q12D,Ads:A2309B2208C4019".to_string(),
-                "S fttf14".to_string(),//
-                "C 3,4".to_string(),//
-                "I 13942,90651,1,37".to_string(),
-                "R 94,12,23,28,28,77,67,49,33,31,279".to_string(),
-                "B igo,1283637600000".to_string(),//
-                "L igo,1283637600000".to_string(),//
-                ],
+        players: game.start_track_players(),
+        seed: game.seed(),
+        track: game.current_track().cloned().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::common::{PlayerInfo, PlayerState::*};
+
+    use super::disagrees;
+
+    #[test]
+    fn empty_slots_never_disagree() {
+        let simulated = PlayerInfo(vec![NotInHole, Left, InHole]);
+        assert!(!disagrees(
+            &simulated,
+            &PlayerInfo(vec![NotInHole, NotInHole, InHole])
+        ));
+        assert!(disagrees(
+            &simulated,
+            &PlayerInfo(vec![NotInHole, Left, NotInHole])
+        ));
     }
 }
@@ -1,20 +1,28 @@
-use std::{ops::Add, time::Instant};
+use std::{
+    ops::Add,
+    time::{Instant, SystemTime},
+};
 
 use protocol::{
     client::ClientToServer,
-    common::{DLobbyType, JoinLeaveReason, NonEmptyOption, SomeAsTab, User},
+    common::{
+        DLobbyType, JoinLeaveReason, KickStyle, NonEmptyOption, PacketNumber, SomeAsTab, User,
+    },
     server::{
         Game, GameBeginStroke, GameGameInfo, GameJoin, GameOwnInfo, GamePart, GamePlayers, GameSay,
-        GameVoteSkip, LobbyCFail, LobbyCancel, LobbyGamelistFull, LobbyJoin, LobbyJoinFromGame,
-        LobbyNC, LobbyOwnJoin, LobbyPart, LobbySay, LobbySayP, LobbySelectNop, LobbySheriffSay,
-        LobbyUsers, Player, ServerToClient, StatusGame, StatusLobby, StatusLobbySelect,
+        GameVoteSkip, KickBan, LobbyCFail, LobbyCancel, LobbyGamelistFull, LobbyJoin,
+        LobbyJoinFromGame, LobbyNC, LobbyOwnJoin, LobbyPart, LobbySay, LobbySayP, LobbySelectNop,
+        LobbySheriffSay, LobbyUsers, Player, ServerToClient, StatusGame, StatusLobby,
+        StatusLobbySelect,
     },
 };
 
 use crate::{
     clients::Client,
+    diagnostics::Desync,
     game::{GameId, GameServer, GameStatus, MinigolfGame},
     server::Server,
+    state::{ConnectionState, MAX_VIOLATIONS},
 };
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -24,6 +32,27 @@ pub enum OnLobbyJoinFrom {
     Lobby,
 }
 
+/// Drops a packet the client may not send in `state` and kicks the client once
+/// it has sent too many.
+pub fn illegal_packet(client: &Client, state: ConnectionState, packet: &ClientToServer) {
+    let violations = client.add_violation();
+    log::warn!(
+        "{} sent {:?} in {:?} ({}/{})",
+        client.name(),
+        packet,
+        state,
+        violations,
+        MAX_VIOLATIONS
+    );
+    if violations >= MAX_VIOLATIONS {
+        log::warn!("kicking {} for illegal packets", client.name());
+        client.send_packet(ServerToClient::KickBan(KickBan {
+            value: KickStyle::KickNow,
+        }));
+        client.disconnect();
+    }
+}
+
 pub fn handle_packets_lobbyselect(
     server: &Server,
     games: &GameServer,
@@ -40,8 +69,9 @@ pub fn handle_packets_lobbyselect(
                 multi: m,
             }))
         }
-        ClientToServer::LobbySelectCspt(_) => todo!(),
-        ClientToServer::LobbySelectQmpt(_) => todo!(),
+        ClientToServer::LobbySelectCspt(_) | ClientToServer::LobbySelectQmpt(_) => {
+            log::warn!("{} sent unimplemented {:?}", client.name(), packet)
+        }
         ClientToServer::LobbySelectSelect(s) => on_lobby_join(
             &server,
             &client,
@@ -63,7 +93,9 @@ pub fn handle_packets_single(
     packet: &ClientToServer,
 ) {
     match packet {
-        ClientToServer::LobbyCspc(_) => todo!(),
+        ClientToServer::LobbyCspc(_) => {
+            log::warn!("{} sent unimplemented {:?}", client.name(), packet)
+        }
         ClientToServer::LobbyTrackSetlist(_) => log::debug!("tracksetlist"),
         _ => {}
     }
@@ -192,7 +224,10 @@ pub fn handle_packets_multi(
             }
             if let Some(game_id) = games.id_from_network_id(packet.network_id) {
                 if let Some(game) = games.get_mut(game_id) {
-                    let _ = game.add_player(client.id().unwrap());
+                    if game.add_player(client.id().unwrap()).is_err() {
+                        log::debug!("{} cannot join full game {}", client.name(), game.name());
+                        return;
+                    }
                     client.set_game(Some(game_id));
                     game_join(server, client, game);
                 }
@@ -287,7 +322,9 @@ pub fn handle_packets_game(
                 message: "lol".to_string(),
             }));
         }
-        ClientToServer::GameStartTurn(_) => todo!(),
+        ClientToServer::GameStartTurn(_) => {
+            log::warn!("{} sent unimplemented {:?}", client.name(), packet)
+        }
         ClientToServer::GameBeginStroke(stroke) => {
             if let Some(game_id) = client.game() {
                 if let Some(game) = games.get(game_id) {
@@ -297,6 +334,13 @@ pub fn handle_packets_game(
                             log::debug!("{} tried to shoot in a wrong turn", client.name());
                             return;
                         }
+                        log::debug!(
+                            "{} shoots at {},{} in mode {}",
+                            client.name(),
+                            stroke.coords.x,
+                            stroke.coords.y,
+                            stroke.coords.mode
+                        );
                         let strokes = game.players()[index].as_ref().unwrap().cur_strokes;
 
                         game.players_mut()[index].as_mut().unwrap().cur_strokes = strokes + 1;
@@ -304,18 +348,22 @@ pub fn handle_packets_game(
                             if c.id() != client.id() {
                                 c.send_packet(ServerToClient::GameBeginStroke(GameBeginStroke {
                                     packet_number: c.next_num(),
-                                    coords: stroke.coords.clone(),
+                                    coords: stroke.coords,
                                     index,
                                 }))
                             }
                         });
+                        game.record(ServerToClient::GameBeginStroke(GameBeginStroke {
+                            packet_number: PacketNumber(0),
+                            coords: stroke.coords,
+                            index,
+                        }));
+                        game.stroke(index, stroke.coords);
                     }
                 }
             }
         }
         ClientToServer::GameEndStroke(endstroke) => {
-            //TODO Check if legit packet
-
             if let Some(game_id) = client.game() {
                 if let Some(game) = games.get(game_id) {
                     if endstroke.index != game.turn() {
@@ -335,29 +383,26 @@ pub fn handle_packets_game(
                             .has_sent_end_stroke = true;
                     }
 
-                    for (i, c) in endstroke.in_hole.chars().enumerate() {
-                        if let Some(player) = game.players_mut().get_mut(i) {
-                            if let Some(player) = player {
-                                if player.in_hole && c == 'f' {
-                                    log::error!(
-                                    "{} tried to set in_hole back to false (clients are not sync)",
-                                    client.name()
-                                )
-                                }
-
-                                if c == 't' {
-                                    if !player.in_hole {
-                                        /*server.broadcast_game_with(Some(game_id), |other_client| {
-                                            ServerToClient::GameResetVoteSkip(GameResetVoteSkip {
-                                                packet_number: other_client.next_num(),
-                                            });
-                                        })*/
-                                    }
-                                    player.in_hole = true;
-                                }
-                            }
+                    // Clients decide until protocol::physics is a port of theirs
+                    for (i, in_hole) in endstroke.in_hole.iter().enumerate() {
+                        if let Some(Some(player)) = game.players_mut().get_mut(i) {
+                            player.in_hole |= in_hole.is_in_hole();
                         }
                     }
+                    if let Some((player, expected)) = game.desync(&endstroke.in_hole) {
+                        server.diagnostics.record_desync(Desync {
+                            network_id: client.network_id(),
+                            received: SystemTime::now(),
+                            game: game.network_id(),
+                            track: game
+                                .current_track()
+                                .map(|track| track.name.clone())
+                                .unwrap_or_default(),
+                            player,
+                            expected,
+                            reported: endstroke.in_hole,
+                        });
+                    }
                 }
             }
         }
@@ -440,6 +485,11 @@ pub fn handle_packets_game(
                     game.remove_player(index);
                     game_changed(server, &games, client.game().unwrap());
                 } else {
+                    game.record(ServerToClient::GamePart(GamePart {
+                        packet_number: PacketNumber(0),
+                        index,
+                        reason: 4,
+                    }));
                     *game.players_mut().get_mut(index).unwrap() = None;
                 }
                 client.set_game(None);
@@ -471,6 +521,11 @@ pub fn handle_packets_game(
                         }
                     }
                 }
+                game.record(ServerToClient::GameSay(GameSay {
+                    packet_number: PacketNumber(0),
+                    index,
+                    message: packet.message.clone(),
+                }));
             }
         }
 
@@ -618,14 +673,88 @@ pub fn send_userlist(server: &Server, lobby: DLobbyType) {
         }));
         client.send_packet(ServerToClient::LobbyOwnJoin(LobbyOwnJoin {
             packet_number: client.next_num(),
-            own_info: User {
-                id_username: format!("3:{}", client.name()),
-                value_1: "r".to_owned(),
-                rank: 999,
-                lang: client.language().to_owned(),
-                value_2: NonEmptyOption(None),
-                value_3: NonEmptyOption(None),
-            },
+            own_info: User::from(client),
         }));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use protocol::{
+        client::{ClientToServer, LobbyCspt},
+        common::{
+            DLobbyType, PacketNumber, Parse, PlayerInfo, PlayerState, TrackType, UserFlags,
+            WaterEvent,
+        },
+        server::ServerToClient,
+        track::{map::TrackMap, Track},
+    };
+
+    use crate::{
+        clients::NetworkId, game::GameServer, initial_handler::NewPlayer, server::Server,
+        tracks::TrackLibrary,
+    };
+
+    use super::handle_packets_game;
+
+    #[test]
+    fn clients_decide_hole_ins() {
+        let mut server = Server::new(flume::bounded(1).1);
+        let (packets_to_send, sent) = flume::unbounded();
+        let id = server.add_client(NewPlayer {
+            network_id: 3,
+            name: "golfer".to_string(),
+            clan: None,
+            seed: 0,
+            language: "en".to_string(),
+            account_flags: UserFlags::default(),
+            sent: 0,
+            received_packets: flume::unbounded().1,
+            packets_to_send,
+        });
+        let mut tracks = TrackLibrary::new();
+        // No hole, so the simulation never puts the ball in
+        tracks.insert(Track {
+            name: "Empty".to_string(),
+            map: TrackMap::new().encode().unwrap(),
+            ..Default::default()
+        });
+        let mut games = GameServer::new(tracks, std::env::temp_dir());
+        let client = server.clients.get(id).unwrap();
+        client.set_lobby(Some(DLobbyType::Solo));
+        let game_id = games.handle_cspt(
+            client,
+            &LobbyCspt {
+                packet_number: PacketNumber(2),
+                num_tracks: 2,
+                track_type: TrackType::All,
+                water_event: WaterEvent::default(),
+            },
+        );
+        client.set_game(Some(game_id));
+        games.handle_rooms(&server);
+
+        for line in [
+            "d 4 game\tbeginstroke\t70q4\n",
+            "d 5 game\tendstroke\t0\tt\n",
+        ] {
+            let packet = ClientToServer::parse(line).unwrap().1;
+            handle_packets_game(&server, &mut games, client, packet);
+        }
+        let desyncs = server.diagnostics.desyncs_for(NetworkId(3));
+        assert_eq!(desyncs.len(), 1);
+        assert_eq!(
+            desyncs[0].expected,
+            PlayerInfo(vec![PlayerState::NotInHole])
+        );
+        assert_eq!(desyncs[0].reported, PlayerInfo(vec![PlayerState::InHole]));
+
+        sent.drain();
+        games.handle_rooms(&server);
+        let end = sent.try_iter().find_map(|packet| match packet {
+            ServerToClient::GameEndStroke(end) => Some(end),
+            _ => None,
+        });
+        assert_eq!(end.unwrap().in_hole, PlayerInfo(vec![PlayerState::InHole]));
+    }
+}
@@ -1,12 +1,14 @@
+use anyhow::bail;
 use flume::{Receiver, Sender};
 use protocol::{
-    client::{ClientToServer, Language, LoginType, TLog, TTLogin, Version},
-    common::{PacketNumber, SomeAsTab},
+    client::{ClientToServer, Language, LoginType, TTLogin, Version},
+    common::{PacketNumber, SomeAsTab, UserFlags},
     server::{BasicInfo, Error, ServerToClient, StatusLobbySelect, StatusLogin, VersOk},
+    version::ProtocolVersion,
 };
 use rand::Rng;
 
-use crate::listener::Worker;
+use crate::{clients::NetworkId, listener::Worker};
 
 pub enum InitialHandling {
     Join(NewPlayer),
@@ -18,6 +20,8 @@ pub struct NewPlayer {
     pub clan: Option<String>,
     pub seed: i32,
     pub language: String,
+    /// Worm or registered, VIP and sheriff badges.
+    pub account_flags: UserFlags,
     pub sent: u32,
 
     pub received_packets: Receiver<ClientToServer>,
@@ -46,32 +50,33 @@ pub async fn handle(worker: &mut Worker) -> anyhow::Result<InitialHandling> {
     worker
         .write_str(&format!("h 1\nc io {}\nc crt 250\nc ctr\n", seed))
         .await?;
+    worker.enable_ciphers(seed);
 
     worker.read::<protocol::client::New>().await?;
     let network_id = worker.id_generator().next_id();
+    worker.set_network_id(NetworkId(network_id));
     log::debug!("new id {} with {} seed", network_id, seed);
     worker
         .write(protocol::server::Id { value: network_id })
         .await?;
     //let packet = worker.read().await?.command; // skip 1
     let version: Version = worker.read::<Version>().await?;
-    if version.version == 35 {
-        //worker.write(ServerToClient::VersOk{Vers})?.await?;
-        worker
-            .write(VersOk {
-                packet_number: PacketNumber(0),
-            })
-            .await?;
-    } else {
+    let Some(protocol_version) = ProtocolVersion::from_number(version.version) else {
         worker
             .write(Error {
                 packet_number: PacketNumber(0),
                 error: protocol::common::DErrorType::VerNotOk,
             })
             .await?;
-    }
-    let _ = worker.read::<TLog>().await?; //TODO: sometimes packets arrive at different order
-
+        bail!("unsupported protocol version {}", version.version);
+    };
+    log::debug!("client speaks protocol version {}", protocol_version);
+    worker.set_protocol_version(protocol_version);
+    worker
+        .write(VersOk {
+            packet_number: PacketNumber(0),
+        })
+        .await?;
     let language = worker.read::<Language>().await?.languge;
     let logintype = worker.read::<LoginType>().await?;
 
@@ -95,6 +100,12 @@ pub async fn handle(worker: &mut Worker) -> anyhow::Result<InitialHandling> {
         Some(username) => username,
         None => generate_username(), //TODO
     };
+    // Guest names start with ~, there are no VIP or sheriff accounts yet
+    let account_flags = if username.starts_with('~') {
+        UserFlags::WORM
+    } else {
+        UserFlags::REGISTERED
+    };
 
     worker
         .write(BasicInfo {
@@ -119,6 +130,7 @@ pub async fn handle(worker: &mut Worker) -> anyhow::Result<InitialHandling> {
         clan: None,
         language,
         seed,
+        account_flags,
         sent,
         received_packets: worker.received_packets(),
         packets_to_send: worker.packets_to_send(),
@@ -1,14 +1,20 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use flume::{Receiver, Sender};
 use futures_lite::FutureExt;
 use protocol::{
-    client::ClientToServer,
-    common::{Packet, Parse},
+    client::{ClientToServer, TLog},
+    codec::{Decoded, MinigolfCodec},
+    common::{Packet, PacketNumber, Parse, Unknown},
+    crypt::{Ciphers, ConnCipher, GameCipher, CIPHER_MAGIC_DEFAULT},
     server::ServerToClient,
+    version::ProtocolVersion,
 };
 use std::ops::Add;
-use std::{fmt::Debug, io, time::Duration};
-use std::{io::ErrorKind, net::SocketAddr};
+use std::{fmt::Debug, io, str::FromStr, time::Duration};
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{
@@ -19,15 +25,90 @@ use tokio::{
 };
 
 use crate::{
-    codec::MinigolfCodec,
+    clients::NetworkId,
+    diagnostics::Diagnostics,
     initial_handler::{self, InitialHandling, NewPlayer},
     playerid::IdGenerator,
 };
 
+/// Which ciphers a connection uses once the handshake has been sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherMode {
+    Plain,
+    Game,
+    Conn,
+    Full,
+}
+
+impl CipherMode {
+    pub fn ciphers(self, seed: i32) -> Ciphers {
+        let game_cipher = matches!(self, CipherMode::Game | CipherMode::Full).then(GameCipher::new);
+        let conn_cipher = matches!(self, CipherMode::Conn | CipherMode::Full)
+            .then(|| ConnCipher::new(CIPHER_MAGIC_DEFAULT, seed));
+        Ciphers::new(game_cipher, conn_cipher)
+    }
+}
+
+impl FromStr for CipherMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plain" | "off" => Ok(CipherMode::Plain),
+            "game" => Ok(CipherMode::Game),
+            "conn" => Ok(CipherMode::Conn),
+            "full" | "on" => Ok(CipherMode::Full),
+            _ => Err(anyhow!("unknown cipher mode {:?}", s)),
+        }
+    }
+}
+
+/// Picks the cipher mode for each accepted connection. Hosts listed in
+/// `plaintext_hosts` always talk plaintext, handy for netcat and the proxy.
+#[derive(Debug, Clone)]
+pub struct CipherConfig {
+    pub mode: CipherMode,
+    pub plaintext_hosts: Vec<IpAddr>,
+}
+
+impl CipherConfig {
+    /// Reads `CIPHERS` (plain, game, conn or full) and the comma separated
+    /// `PLAINTEXT_HOSTS`. Plain by default: the ciphers haven't been checked
+    /// against the original client yet.
+    pub fn from_env() -> Result<Self> {
+        let mode = match std::env::var("CIPHERS") {
+            Ok(mode) => mode.parse()?,
+            Err(_) => CipherMode::Plain,
+        };
+        let plaintext_hosts = match std::env::var("PLAINTEXT_HOSTS") {
+            Ok(hosts) => hosts
+                .split(',')
+                .filter(|host| !host.is_empty())
+                .map(|host| host.trim().parse().context("invalid PLAINTEXT_HOSTS"))
+                .collect::<Result<_>>()?,
+            Err(_) => Vec::new(),
+        };
+        Ok(Self {
+            mode,
+            plaintext_hosts,
+        })
+    }
+
+    pub fn mode_for(&self, addr: &SocketAddr) -> CipherMode {
+        if self.plaintext_hosts.contains(&addr.ip()) {
+            CipherMode::Plain
+        } else {
+            self.mode
+        }
+    }
+}
+
 pub struct Listener {
     listener: TcpListener,
     new_players: Sender<NewPlayer>,
     id_generator: IdGenerator,
+    diagnostics: Diagnostics,
+    cipher_config: CipherConfig,
 }
 
 pub struct Worker {
@@ -37,6 +118,7 @@ pub struct Worker {
     received_packets_rx: Receiver<ClientToServer>,
     new_players: Sender<NewPlayer>,
     id_generator: IdGenerator,
+    cipher_mode: CipherMode,
 }
 impl Worker {
     fn new(
@@ -44,12 +126,14 @@ impl Worker {
         addr: SocketAddr,
         new_players: Sender<NewPlayer>,
         id_generator: IdGenerator,
+        diagnostics: Diagnostics,
+        cipher_mode: CipherMode,
     ) -> Worker {
         let (reader, writer) = stream.into_split();
 
         let (received_packets_tx, received_packets_rx) = flume::bounded(32);
         let (packets_to_send_tx, packets_to_send_rx) = flume::unbounded();
-        let reader = Reader::new(reader, received_packets_tx);
+        let reader = Reader::new(reader, received_packets_tx, diagnostics);
         let writer = Writer::new(writer, packets_to_send_rx);
 
         Worker {
@@ -59,6 +143,7 @@ impl Worker {
             received_packets_rx,
             new_players,
             id_generator,
+            cipher_mode,
         }
     }
     pub fn start(self) {
@@ -117,10 +202,35 @@ impl Worker {
         self.writer.write_str(&str).await
     }
 
+    /// Switches both directions to the ciphers of this connection's mode. Call
+    /// right after sending the `c io <seed>` handshake.
+    pub fn enable_ciphers(&mut self, seed: i32) {
+        log::debug!("using {:?} ciphers", self.cipher_mode);
+        let ciphers = self.cipher_mode.ciphers(seed);
+        self.reader.codec.set_ciphers(ciphers.clone());
+        self.writer.ciphers = ciphers;
+    }
+
+    /// Reloads the game cipher with the command table of `version`.
+    pub fn set_protocol_version(&mut self, version: ProtocolVersion) {
+        if self.writer.ciphers.game_cipher.is_none() {
+            return;
+        }
+        let mut ciphers = self.writer.ciphers.clone();
+        ciphers.set_game_cipher(Some(version.game_cipher()));
+        self.reader.codec.set_ciphers(ciphers.clone());
+        self.writer.ciphers = ciphers;
+    }
+
     pub fn id_generator(&mut self) -> IdGenerator {
         self.id_generator.clone()
     }
 
+    /// Client logs read from now on are recorded for `network_id`.
+    pub fn set_network_id(&mut self, network_id: NetworkId) {
+        self.reader.network_id = Some(network_id);
+    }
+
     pub fn packets_to_send(&mut self) -> Sender<ServerToClient> {
         self.packets_to_send_tx.clone()
     }
@@ -131,7 +241,12 @@ impl Worker {
 }
 
 impl Listener {
-    pub async fn start(new_players: Sender<NewPlayer>, id_generator: IdGenerator) -> Result<()> {
+    pub async fn start(
+        new_players: Sender<NewPlayer>,
+        id_generator: IdGenerator,
+        diagnostics: Diagnostics,
+        cipher_config: CipherConfig,
+    ) -> Result<()> {
         let listener = TcpListener::bind("0.0.0.0:4242")
             .await
             .context("failed to bind to port - maybe a server is already running?")?;
@@ -139,6 +254,8 @@ impl Listener {
             listener,
             new_players,
             id_generator,
+            diagnostics,
+            cipher_config,
         };
 
         log::info!("Server is listening on :4242",);
@@ -162,6 +279,8 @@ impl Listener {
             addr,
             self.new_players.clone(),
             self.id_generator.clone(),
+            self.diagnostics.clone(),
+            self.cipher_config.mode_for(&addr),
         );
         worker.start();
     }
@@ -173,16 +292,31 @@ struct Reader {
     received_packets: Sender<ClientToServer>,
     read: u32,
     codec: MinigolfCodec,
+    diagnostics: Diagnostics,
+    network_id: Option<NetworkId>,
 }
 
 impl Reader {
-    pub fn new(stream: OwnedReadHalf, received_packets: Sender<ClientToServer>) -> Self {
+    pub fn new(
+        stream: OwnedReadHalf,
+        received_packets: Sender<ClientToServer>,
+        diagnostics: Diagnostics,
+    ) -> Self {
         Self {
             stream,
             buffer: [0; 512],
             received_packets,
-            read: 3, //TODO
+            read: 0,
             codec: MinigolfCodec::new(),
+            diagnostics,
+            network_id: None,
+        }
+    }
+
+    fn record_log(&self, log: TLog) {
+        match self.network_id {
+            Some(network_id) => self.diagnostics.record(network_id, log),
+            None => log::warn!("client log before the handshake: {:?}", log),
         }
     }
     pub fn add_num(&mut self) -> u32 {
@@ -190,13 +324,30 @@ impl Reader {
         self.read
     }
 
+    /// Numbered from the handshake on, `d 1 version` is the first. Skipped lines
+    /// count too, the client numbered them all the same.
+    fn count(&mut self, number: Option<PacketNumber>) {
+        if let Some(number) = number {
+            if number.0 != self.add_num() {
+                log::error!("Wrong packet_number {:?} {:?}", number.0, self.read);
+            }
+        }
+    }
+
     pub async fn run(mut self) -> anyhow::Result<()> {
         loop {
             let packet: ClientToServer = self.read().await?;
-            if let Some(number) = packet.packet_number() {
-                if number.0 != self.add_num() {
-                    log::error!("Wrong packet_number {:?} {:?}\n", number.0, self.read);
-                }
+            if let ClientToServer::Unknown(unknown) = &packet {
+                log::warn!(
+                    "unknown packet {:?} {:?}",
+                    unknown.packet_number,
+                    unknown.as_string()
+                );
+                continue;
+            }
+            if let ClientToServer::TLog(log) = packet {
+                self.record_log(log);
+                continue;
             }
             log::trace!("recv :{:?}", packet);
             let result = self.received_packets.send_async(packet).await;
@@ -213,8 +364,26 @@ impl Reader {
         T: Parse + Packet,
     {
         loop {
-            if let Some(packet) = self.codec.next_packet::<T>()? {
-                return Ok(packet);
+            match self.codec.next_packet::<T>()? {
+                Some(Decoded::Packet(packet)) => {
+                    self.count(packet.packet_number());
+                    return Ok(packet);
+                }
+                Some(Decoded::Unknown(line)) => {
+                    self.count(line_number(&line));
+                    // Clients send logs whenever they like, also mid handshake
+                    match TLog::parse(&line) {
+                        Ok(("", log)) => self.record_log(log),
+                        _ => log::warn!("skipping unknown packet {:?}", line),
+                    }
+                    continue;
+                }
+                Some(Decoded::Malformed { line, reason }) => {
+                    self.count(line_number(&line));
+                    log::warn!("skipping malformed packet {:?}: {}", line, reason);
+                    continue;
+                }
+                None => {}
             }
 
             let duration = Duration::from_secs(10);
@@ -227,7 +396,7 @@ impl Reader {
             }
             let bytes = &self.buffer[..bytes_read];
 
-            self.codec.accept(&bytes);
+            self.codec.accept(bytes);
         }
     }
 }
@@ -236,6 +405,7 @@ struct Writer {
     stream: OwnedWriteHalf,
     packets_to_send: Receiver<ServerToClient>,
     buffer: Vec<u8>,
+    ciphers: Ciphers,
 }
 
 impl Writer {
@@ -244,6 +414,7 @@ impl Writer {
             stream,
             packets_to_send,
             buffer: Vec::new(),
+            ciphers: Ciphers::none(),
         }
     }
 
@@ -256,20 +427,25 @@ impl Writer {
 
     pub async fn write(&mut self, packet: impl Parse + Packet + Debug) -> anyhow::Result<()> {
         log::trace!("send: {:?}", packet);
-        self.buffer = packet.as_string().into();
+        self.buffer = self.ciphers.encrypt(&packet.as_string()).into();
         self.stream.write_all(&self.buffer).await?;
         self.buffer.clear();
         Ok(())
     }
 
     pub async fn write_str(&mut self, str: &str) -> anyhow::Result<()> {
-        self.buffer = str.into();
+        self.buffer = self.ciphers.encrypt(str).into();
         self.stream.write_all(&self.buffer).await?;
         self.buffer.clear();
         Ok(())
     }
 }
 
+/// The number of a `d <number> ` line that didn't decode into a packet.
+fn line_number(line: &str) -> Option<PacketNumber> {
+    Unknown::parse(line).ok()?.1.packet_number
+}
+
 fn disconnected_message(e: anyhow::Error) -> String {
     if let Some(io_error) = e.downcast_ref::<io::Error>() {
         if io_error.kind() == ErrorKind::UnexpectedEof {
@@ -278,3 +454,41 @@ fn disconnected_message(e: anyhow::Error) -> String {
     }
     format!("{:?}", e)
 }
+
+#[cfg(test)]
+mod tests {
+    use protocol::client::ClientToServer;
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::Reader;
+    use crate::diagnostics::Diagnostics;
+
+    #[tokio::test]
+    async fn malformed_lines_are_numbered() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read_half, _write_half) = stream.into_split();
+        let (received, _) = flume::unbounded();
+        let mut reader = Reader::new(read_half, received, Diagnostics::new());
+
+        client
+            .write_all(b"d 1 version\t35\nd 2 game\tendstroke\t1\tfxf\nd 3 language\ten\n")
+            .await
+            .unwrap();
+        let packet: ClientToServer = reader.read().await.unwrap();
+        assert!(matches!(packet, ClientToServer::Version(_)), "{:?}", packet);
+        let packet: ClientToServer = reader.read().await.unwrap();
+        assert!(
+            matches!(packet, ClientToServer::Language(_)),
+            "{:?}",
+            packet
+        );
+        assert_eq!(reader.read, 3);
+    }
+}
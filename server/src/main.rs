@@ -1,14 +1,22 @@
-use std::time::{Duration, Instant};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use crate::server::Server;
 use anyhow::Result;
+use bots::Bots;
 use game::GameServer;
+use listener::CipherConfig;
 use protocol::client::{ClientToServer, Pong};
+use protocol::common::DLobbyType;
+use state::ConnectionState;
 use tickloop::TickLoop;
+use tracks::TrackLibrary;
 
+mod bots;
 mod clients;
-//mod crypt;
-mod codec;
+mod diagnostics;
 mod filter;
 mod game;
 mod handle_packets;
@@ -16,13 +24,19 @@ mod initial_handler;
 mod listener;
 mod playerid;
 mod server;
+mod state;
 mod tickloop;
+mod tracks;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
-    let mut server = Server::bind().await?;
-    let mut games = GameServer::new();
+    let tracks_dir = std::env::var("TRACKS_DIR").unwrap_or_else(|_| "tracks".to_string());
+    let tracks = TrackLibrary::load(tracks_dir)?;
+    let mut server = Server::bind(CipherConfig::from_env()?).await?;
+    let replays_dir = std::env::var("REPLAYS_DIR").unwrap_or_else(|_| "replays".to_string());
+    let mut games = GameServer::new(tracks, replays_dir);
+    let mut bots = Bots::from_env(&mut server)?;
 
     let interval = Duration::from_secs(5);
 
@@ -30,36 +44,42 @@ async fn main() -> Result<()> {
         server.accept_new_players();
         server.remove_old_players(&mut games);
         games.handle_rooms(&server);
+        bots.tick();
         for client in server.clients.iter() {
             for packet in client.received_packets() {
                 log::debug!("handling: {:?}", packet);
+                let state = client.state();
+                if !state.allows(&packet) {
+                    handle_packets::illegal_packet(client, state, &packet);
+                    continue;
+                }
                 if matches!(packet, ClientToServer::Pong(Pong {})) {
                     client.set_pong();
-                } else if client.lobby_select() {
-                    handle_packets::handle_packets_lobbyselect(
-                        &server, &mut games, &client, packet,
-                    );
-                } else if client.lobby().is_some() && client.game().is_none() {
-                    match client.lobby().unwrap() {
-                        protocol::common::DLobbyType::Solo
-                        | protocol::common::DLobbyType::SoloIncognito => {
-                            handle_packets::handle_packets_single(
+                    continue;
+                }
+                match state {
+                    ConnectionState::LobbySelect => {
+                        handle_packets::handle_packets_lobbyselect(&server, &games, client, packet)
+                    }
+                    ConnectionState::Lobby(lobby) => {
+                        match lobby {
+                            DLobbyType::Solo | DLobbyType::SoloIncognito => {
+                                handle_packets::handle_packets_single(
+                                    &server, &mut games, client, &packet,
+                                )
+                            }
+                            DLobbyType::Duo => handle_packets::handle_packets_dual(
                                 &server, &mut games, client, &packet,
-                            )
-                        }
-                        protocol::common::DLobbyType::Duo => handle_packets::handle_packets_dual(
-                            &server, &mut games, client, &packet,
-                        ),
-                        protocol::common::DLobbyType::Multi => {
-                            handle_packets::handle_packets_multi(
+                            ),
+                            DLobbyType::Multi => handle_packets::handle_packets_multi(
                                 &server, &mut games, client, &packet,
-                            )
+                            ),
                         }
+                        handle_packets::handle_packets_lobby(&server, &mut games, client, packet);
+                    }
+                    ConnectionState::Game { .. } => {
+                        handle_packets::handle_packets_game(&server, &mut games, client, packet)
                     }
-                    handle_packets::handle_packets_lobby(&server, &mut games, &client, packet);
-                } else {
-                    //we should be in game
-                    handle_packets::handle_packets_game(&server, &mut games, &client, packet);
                 }
             }
         }
@@ -68,7 +88,8 @@ async fn main() -> Result<()> {
             server.broadcast_ping();
         }
         false
-    });
+    })
+    .with_stats_file(std::env::var_os("TICK_STATS").map(PathBuf::from));
     tickloop.run();
 
     Ok(())
@@ -3,34 +3,53 @@ use crate::game::{GameStatus, MinigolfGame};
 use crate::handle_packets::game_changed;
 use crate::{
     clients::{Client, ClientId, Clients},
+    diagnostics::Diagnostics,
     initial_handler::NewPlayer,
-    listener::Listener,
+    listener::{CipherConfig, Listener},
     playerid::IdGenerator,
 };
 use anyhow::Result;
 use flume::Receiver;
-use protocol::common::DLobbyType;
+use protocol::common::{DLobbyType, PacketNumber};
 use protocol::server::GamePart;
 use protocol::server::LobbyPart;
 use protocol::server::ServerToClient;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 pub struct Server {
     pub clients: Clients,
+    /// Logs clients sent with `s tlog`.
+    pub diagnostics: Diagnostics,
     new_players: Receiver<NewPlayer>,
+    id_generator: IdGenerator,
     pub last_ping: Instant,
+    /// The ping before `last_ping`, clients that haven't answered it are gone.
+    previous_ping: Instant,
 }
 
 impl Server {
-    pub async fn bind() -> Result<Self> {
+    pub async fn bind(cipher_config: CipherConfig) -> Result<Self> {
         let (new_players_tx, new_players) = flume::bounded(4);
-        let id_generator = IdGenerator::new();
-        Listener::start(new_players_tx, id_generator.clone()).await?;
+        let server = Self::new(new_players);
+        Listener::start(
+            new_players_tx,
+            server.id_generator.clone(),
+            server.diagnostics.clone(),
+            cipher_config,
+        )
+        .await?;
+        Ok(server)
+    }
 
-        Ok(Self {
+    /// A server that takes its players from `new_players` instead of a listener.
+    pub fn new(new_players: Receiver<NewPlayer>) -> Self {
+        Self {
             clients: Clients::new(),
+            diagnostics: Diagnostics::new(),
             new_players,
+            id_generator: IdGenerator::new(),
             last_ping: Instant::now(),
-        })
+            previous_ping: Instant::now(),
+        }
     }
 
     pub fn accept_new_players(&mut self) -> Vec<ClientId> {
@@ -40,23 +59,27 @@ impl Server {
             /*if let Some(old_client) = self.clients.iter().find(|x| x.uuid() == player.uuid) {
                 old_client.disconnect("Logged in from another location!");
             }*/
-            let id = self.create_client(player);
-            clients.push(id);
-            self.clients.get_mut(id).unwrap().set_client_id(id);
+            clients.push(self.add_client(player));
         }
         clients
     }
 
-    pub fn remove_old_players(&mut self, games: &mut GameServer) {
-        let interval = Duration::from_secs(5);
+    /// Adds a player that has finished the handshake, or a bot.
+    pub fn add_client(&mut self, player: NewPlayer) -> ClientId {
+        let id = self.create_client(player);
+        self.clients.get_mut(id).unwrap().set_client_id(id);
+        id
+    }
+
+    pub fn next_network_id(&mut self) -> usize {
+        self.id_generator.next_id()
+    }
 
+    pub fn remove_old_players(&mut self, games: &mut GameServer) {
         let clients_to_remove: Vec<_> = self
             .clients
             .iter()
-            .filter(|client| {
-                client.disconnected()
-                    || self.last_ping.duration_since(client.last_pong()) > interval
-            })
+            .filter(|client| client.disconnected() || client.last_pong() < self.previous_ping)
             .map(|client| client.id())
             .collect();
 
@@ -93,6 +116,11 @@ impl Server {
                                     reason: 4,
                                 }))
                             });
+                            game.record(ServerToClient::GamePart(GamePart {
+                                packet_number: PacketNumber(0),
+                                index,
+                                reason: 4,
+                            }));
                             if let Some(player) = game.players_mut().get_mut(index) {
                                 *player = None;
                             }
@@ -154,6 +182,53 @@ impl Server {
 
     pub fn broadcast_ping(&mut self) {
         self.broadcast_with(|client| client.send_ping());
+        self.previous_ping = self.last_ping;
         self.last_ping = Instant::now();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use protocol::client::ClientToServer;
+
+    use crate::{bots::Bots, game::GameServer, tracks::TrackLibrary};
+
+    use super::Server;
+
+    fn handle_pongs(server: &Server) {
+        for client in server.clients.iter() {
+            for packet in client.received_packets() {
+                if matches!(packet, ClientToServer::Pong(_)) {
+                    client.set_pong();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ping_timeout() {
+        let mut server = Server::new(flume::bounded(1).1);
+        let mut games = GameServer::new(TrackLibrary::new(), std::env::temp_dir());
+        let mut bots = Bots::new();
+        bots.spawn(&mut server, "~bot-1", "easy".parse().unwrap());
+        // Never ticked, so it never answers
+        let mut silent = Bots::new();
+        silent.spawn(&mut server, "~bot-2", "easy".parse().unwrap());
+        thread::sleep(Duration::from_millis(1));
+
+        // Checked in the same tick the ping goes out, before anyone could answer
+        server.broadcast_ping();
+        server.remove_old_players(&mut games);
+        assert_eq!(server.clients.iter().count(), 2);
+
+        bots.tick();
+        handle_pongs(&server);
+        thread::sleep(Duration::from_millis(1));
+        server.broadcast_ping();
+        server.remove_old_players(&mut games);
+        assert!(server.clients.client_from_name("~bot-1").is_some());
+        assert!(server.clients.client_from_name("~bot-2").is_none());
+    }
+}
@@ -0,0 +1,137 @@
+use protocol::{client::ClientToServer, common::DLobbyType};
+
+use crate::game::GameId;
+
+/// Illegal packets a client may send before it is kicked.
+pub const MAX_VIOLATIONS: u32 = 10;
+
+/// Where a connection is after the handshake, decides which packets are legal.
+///
+/// The handshake itself (`new`, `version`, `language`, login) is read in a fixed
+/// order by `initial_handler`, so clients start in `LobbySelect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    LobbySelect,
+    Lobby(DLobbyType),
+    Game { lobby: DLobbyType, game: GameId },
+}
+
+impl ConnectionState {
+    pub fn lobby(&self) -> Option<DLobbyType> {
+        match self {
+            ConnectionState::LobbySelect => None,
+            ConnectionState::Lobby(lobby) | ConnectionState::Game { lobby, .. } => Some(*lobby),
+        }
+    }
+
+    pub fn game(&self) -> Option<GameId> {
+        match self {
+            ConnectionState::Game { game, .. } => Some(*game),
+            _ => None,
+        }
+    }
+
+    /// Whether a client in this state may send `packet`.
+    pub fn allows(&self, packet: &ClientToServer) -> bool {
+        use ClientToServer as P;
+
+        if matches!(packet, P::Pong(_) | P::Quit(_) | P::TLog(_)) {
+            return true;
+        }
+        match self {
+            ConnectionState::LobbySelect => matches!(
+                packet,
+                P::LobbySelectRnop(_)
+                    | P::LobbySelectCspt(_)
+                    | P::LobbySelectQmpt(_)
+                    | P::LobbySelectSelect(_)
+            ),
+            ConnectionState::Lobby(lobby) => {
+                let any_lobby = matches!(
+                    packet,
+                    P::LobbySelectRnop(_)
+                        | P::LobbySelectSelect(_)
+                        | P::LobbySelect(_)
+                        | P::LobbyBack(_)
+                        | P::LobbySay(_)
+                        | P::LobbySayP(_)
+                        | P::LobbyQuit(_)
+                        | P::LobbyTrackSetlist(_)
+                );
+                any_lobby
+                    || match lobby {
+                        DLobbyType::Solo | DLobbyType::SoloIncognito => {
+                            matches!(
+                                packet,
+                                P::LobbyCspt(_) | P::LobbyCspc(_) | P::LobbyTracklist(_)
+                            )
+                        }
+                        DLobbyType::Duo => matches!(
+                            packet,
+                            P::LobbyNc(_)
+                                | P::LobbyChallenge(_)
+                                | P::LobbyCFail(_)
+                                | P::LobbyCancel(_)
+                                | P::LobbyAccept(_)
+                        ),
+                        DLobbyType::Multi => matches!(packet, P::LobbyCmpt(_) | P::LobbyJmpt(_)),
+                    }
+            }
+            ConnectionState::Game { .. } => matches!(
+                packet,
+                P::GameRate(_)
+                    | P::GameStartTurn(_)
+                    | P::GameBeginStroke(_)
+                    | P::GameEndStroke(_)
+                    | P::GameBackToPrivate(_)
+                    | P::GameRejectAccept(_)
+                    | P::GameSkip(_)
+                    | P::GameNewGame(_)
+                    | P::GameVoteSkip(_)
+                    | P::GameJoin(_)
+                    | P::GameBack(_)
+                    | P::GameSay(_)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::{
+        client::ClientToServer,
+        common::{DLobbyType, Parse},
+    };
+
+    use super::ConnectionState;
+    use crate::game::GameId;
+
+    fn packet(line: &str) -> ClientToServer {
+        ClientToServer::parse(line).unwrap().1
+    }
+
+    #[test]
+    fn allowed_packets() {
+        let select = packet("d 4 lobbyselect\tselect\tx\n");
+        let say = packet("d 5 lobby\tsay\tl\thello\n");
+        let challenge = packet("d 6 lobby\tnc\tt\n");
+        let version = packet("d 1 version\t35\n");
+
+        assert!(ConnectionState::LobbySelect.allows(&select));
+        assert!(!ConnectionState::LobbySelect.allows(&say));
+        assert!(ConnectionState::Lobby(DLobbyType::Multi).allows(&say));
+        assert!(ConnectionState::Lobby(DLobbyType::Duo).allows(&challenge));
+        assert!(!ConnectionState::Lobby(DLobbyType::Multi).allows(&challenge));
+
+        let game = ConnectionState::Game {
+            lobby: DLobbyType::Multi,
+            game: GameId(0),
+        };
+        assert!(!game.allows(&say));
+        assert!(game.allows(&packet("d 7 game\tsay\thi\n")));
+        for state in [ConnectionState::LobbySelect, game] {
+            assert!(!state.allows(&version));
+            assert!(state.allows(&packet("c pong\n")));
+        }
+    }
+}
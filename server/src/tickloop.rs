@@ -1,4 +1,8 @@
-use std::time::{Duration, Instant};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 /// Number of updates (ticks) to do per second.
 pub const TPS: u32 = 5;
@@ -7,10 +11,30 @@ pub const TICK_MILLIS: u32 = 1000 / TPS;
 /// The duration of a tick.
 pub const TICK_DURATION: Duration = Duration::from_millis(TICK_MILLIS as u64);
 
+/// Ticks that took longer than [`TICK_DURATION`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Overruns {
+    pub count: u64,
+    pub worst: Duration,
+}
+
+impl Overruns {
+    /// `overruns <count>` and `worst_ms <millis>` lines, read by the load test.
+    pub fn as_string(&self) -> String {
+        format!(
+            "overruns {}\nworst_ms {}\n",
+            self.count,
+            self.worst.as_millis()
+        )
+    }
+}
+
 /// Utility to invoke a function in a tick loop, once
 /// every 50ms.
 pub struct TickLoop {
     function: Box<dyn FnMut() -> bool>,
+    overruns: Overruns,
+    stats_file: Option<PathBuf>,
 }
 
 impl TickLoop {
@@ -20,11 +44,20 @@ impl TickLoop {
     pub fn new(function: impl FnMut() -> bool + 'static) -> Self {
         Self {
             function: Box::new(function),
+            overruns: Overruns::default(),
+            stats_file: None,
         }
     }
 
+    /// Keeps the [`Overruns`] so far in `path`, rewritten on every overrun.
+    pub fn with_stats_file(mut self, path: Option<PathBuf>) -> Self {
+        self.stats_file = path;
+        self
+    }
+
     /// Runs the tick loop until the callback returns `true`.
     pub fn run(mut self) {
+        self.write_stats();
         loop {
             let start = Instant::now();
             let should_exit = (self.function)();
@@ -34,10 +67,54 @@ impl TickLoop {
 
             let elapsed = start.elapsed();
             if elapsed > TICK_DURATION {
-                log::warn!("Tick took too long ({:?})", elapsed);
+                log::warn!("Tick took too long ({}ms)", elapsed.as_millis());
+                self.overruns.count += 1;
+                self.overruns.worst = self.overruns.worst.max(elapsed);
+                self.write_stats();
             } else {
                 std::thread::sleep(TICK_DURATION - elapsed);
             }
         }
     }
+
+    /// Replaces the stats file in one rename, so readers never see half of it.
+    fn write_stats(&self) {
+        let Some(path) = &self.stats_file else {
+            return;
+        };
+        let partial = path.with_extension("partial");
+        let written = fs::write(&partial, self.overruns.as_string())
+            .and_then(|()| fs::rename(&partial, path));
+        if let Err(e) = written {
+            log::warn!("cannot write tick stats to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread::sleep};
+
+    use super::{TickLoop, TICK_DURATION};
+
+    #[test]
+    fn counts_overruns() {
+        let path = std::env::temp_dir().join(format!("tick-stats-{}", std::process::id()));
+        let mut ticks = 0;
+        TickLoop::new(move || {
+            ticks += 1;
+            if ticks == 1 {
+                sleep(TICK_DURATION * 2);
+            }
+            ticks == 2
+        })
+        .with_stats_file(Some(path.clone()))
+        .run();
+
+        let stats = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let worst = stats.strip_prefix("overruns 1\nworst_ms ").unwrap();
+        let worst: u128 = worst.trim_end().parse().unwrap();
+        assert!(worst >= TICK_DURATION.as_millis() * 2, "{}", stats);
+    }
 }
@@ -0,0 +1,135 @@
+use std::{collections::HashMap, fs, path::Path, rc::Rc};
+
+use anyhow::{bail, Context, Result};
+use protocol::{common::TrackType, track::Track};
+use rand::seq::SliceRandom;
+
+/// All tracks the server can hand out, indexed by category.
+#[derive(Default)]
+pub struct TrackLibrary {
+    tracks: Vec<Rc<Track>>,
+    by_type: HashMap<TrackType, Vec<Rc<Track>>>,
+}
+
+impl TrackLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every `*.track` file in `dir`. Files that fail to parse are skipped.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut library = Self::new();
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("failed to read track directory {}", dir.display()))?;
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "track"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let track = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|input| input.parse::<Track>());
+            match track {
+                Ok(track) => library.insert(track),
+                Err(e) => log::warn!("skipping track {}: {:?}", path.display(), e),
+            }
+        }
+
+        if library.is_empty() {
+            bail!("no tracks found in {}", dir.display());
+        }
+        log::info!("Loaded {} tracks from {}", library.len(), dir.display());
+        Ok(library)
+    }
+
+    pub fn insert(&mut self, track: Track) {
+        let track = Rc::new(track);
+        for category in &track.categories {
+            self.by_type
+                .entry(*category)
+                .or_default()
+                .push(track.clone());
+        }
+        self.tracks.push(track);
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Picks `num_tracks` distinct tracks of `track_type`. Falls back to the whole
+    /// library if the category is empty and repeats tracks if there are not enough.
+    pub fn pick(&self, track_type: TrackType, num_tracks: usize) -> Vec<Rc<Track>> {
+        let candidates = match self.by_type.get(&track_type) {
+            Some(tracks) if track_type != TrackType::All => tracks,
+            _ => {
+                if track_type != TrackType::All {
+                    log::warn!("no tracks of type {:?}, using all tracks", track_type);
+                }
+                &self.tracks
+            }
+        };
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut picked = Vec::with_capacity(num_tracks);
+        while picked.len() < num_tracks {
+            let amount = (num_tracks - picked.len()).min(candidates.len());
+            picked.extend(candidates.choose_multiple(&mut rng, amount).cloned());
+        }
+        picked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use protocol::{common::TrackType, track::Track};
+
+    use super::TrackLibrary;
+
+    fn track(name: &str, categories: &str) -> Track {
+        format!("V 1\nA test\nN {}\nT BA2Q\nC {}\n", name, categories)
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_track_file() {
+        let track: Track = include_str!("../tracks/test.track").parse().unwrap();
+        assert_eq!(track.name, "Test");
+        assert_eq!(
+            track.categories,
+            vec![TrackType::Modern, TrackType::HoleInOne]
+        );
+    }
+
+    #[test]
+    fn pick_distinct_tracks() {
+        let mut library = TrackLibrary::new();
+        for i in 0..5 {
+            library.insert(track(&i.to_string(), "1"));
+        }
+        library.insert(track("long", "6"));
+
+        let picked = library.pick(TrackType::Basic, 5);
+        let names: HashSet<_> = picked.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names.len(), 5);
+        assert!(!names.contains("long"));
+
+        assert_eq!(library.pick(TrackType::Long, 3).len(), 3);
+        assert_eq!(library.pick(TrackType::HoleInOne, 2).len(), 2);
+        assert_eq!(library.pick(TrackType::All, 6).len(), 6);
+    }
+}
@@ -0,0 +1,88 @@
+use protocol::track::map::{Color, Element, Special, Tile, TrackMap, MAP_WIDTH};
+
+/// Renders one character per tile.
+pub fn render(map: &TrackMap) -> String {
+    let mut output = String::with_capacity((MAP_WIDTH + 1) * protocol::track::map::MAP_HEIGHT);
+    for (x, _, tile) in map.tiles() {
+        output.push(tile_char(tile));
+        if x == MAP_WIDTH - 1 {
+            output.push('\n');
+        }
+    }
+    output
+}
+
+fn tile_char(tile: &Tile) -> char {
+    match *tile {
+        Tile::Special { special, .. } => special_char(special),
+        Tile::Normal {
+            shape,
+            foreground,
+            background,
+        } => {
+            let foreground = Element::from(foreground);
+            let background = Element::from(background);
+            // A partly walled tile still blocks the ball, show the wall
+            if shape != 0 && background.is_wall() && !foreground.is_wall() {
+                element_char(background)
+            } else {
+                element_char(foreground)
+            }
+        }
+        Tile::Empty { foreground, .. } => element_char(Element::from(foreground)),
+    }
+}
+
+fn element_char(element: Element) -> char {
+    match element {
+        Element::Grass => '.',
+        Element::Dirt => ':',
+        Element::Mud => ',',
+        Element::Ice => '=',
+        Element::Downhill { direction } => ['^', '/', '>', '\\', 'v', 'L', '<', 'J']
+            .get(direction as usize)
+            .copied()
+            .unwrap_or('?'),
+        Element::Water => '~',
+        Element::Acid => '%',
+        Element::WaterSwamp => 'w',
+        Element::AcidSwamp => 'a',
+        Element::Block => '#',
+        Element::StickyBlock => '$',
+        Element::BouncyBlock => '&',
+        Element::Other(_) => '?',
+    }
+}
+
+fn special_char(special: Special) -> char {
+    let color_char = |color: Color, chars: [char; 4]| chars[color as usize];
+    match special {
+        Special::Start => 'S',
+        Special::Hole => 'O',
+        Special::FakeHole => 'o',
+        Special::MovableBlock => 'M',
+        Special::Mine { big: false, .. } => '*',
+        Special::Mine { big: true, .. } => '@',
+        Special::TeleportStart(color) => color_char(color, ['B', 'R', 'Y', 'G']),
+        Special::TeleportExit(color) => color_char(color, ['b', 'r', 'y', 'g']),
+        Special::BreakableBlock { .. } => 'X',
+        Special::MagnetAttract => '+',
+        Special::MagnetRepel => '-',
+        Special::PlayerStart(color) => color_char(color, ['1', '2', '3', '4']),
+        Special::Other(_) => '!',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::track::Track;
+
+    #[test]
+    fn snapshot_test_track() {
+        let track: Track = include_str!("../../server/tracks/test.track")
+            .parse()
+            .unwrap();
+        let map = track.decode_map().unwrap();
+        assert_eq!(super::render(&map), include_str!("../snapshots/test.txt"));
+    }
+}
@@ -0,0 +1,142 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::Result;
+use protocol::track::map::{
+    Color, Element, Special, Tile, TrackMap, MAP_HEIGHT, MAP_WIDTH, TILE_SIZE,
+};
+
+pub const IMAGE_WIDTH: usize = MAP_WIDTH * TILE_SIZE;
+pub const IMAGE_HEIGHT: usize = MAP_HEIGHT * TILE_SIZE;
+
+type Rgb = [u8; 3];
+
+/// RGB image of the whole map, 735x375 pixels like the client draws it.
+pub struct Image {
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn render(map: &TrackMap) -> Self {
+        let mut image = Image {
+            pixels: vec![[0; 3]; IMAGE_WIDTH * IMAGE_HEIGHT],
+        };
+        for (x, y, tile) in map.tiles() {
+            image.draw_tile(x * TILE_SIZE, y * TILE_SIZE, tile);
+        }
+        image
+    }
+
+    pub fn write_png(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, IMAGE_WIDTH as u32, IMAGE_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels.concat())?;
+        Ok(())
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Rgb) {
+        self.pixels[y * IMAGE_WIDTH + x] = color;
+    }
+
+    fn draw_tile(&mut self, left: usize, top: usize, tile: &Tile) {
+        for dy in 0..TILE_SIZE {
+            for dx in 0..TILE_SIZE {
+                let color = tile_pixel(tile, dx, dy);
+                self.set(left + dx, top + dy, color);
+            }
+        }
+    }
+}
+
+fn tile_pixel(tile: &Tile, dx: usize, dy: usize) -> Rgb {
+    match *tile {
+        Tile::Normal {
+            shape,
+            foreground,
+            background,
+        } => {
+            // The client takes shape masks from its sprite sheet, which we do not
+            // have. Dither the foreground over the background instead.
+            if shape == 0 || (dx + dy).is_multiple_of(2) {
+                element_color(Element::from(foreground))
+            } else {
+                element_color(Element::from(background))
+            }
+        }
+        Tile::Empty { foreground, .. } => element_color(Element::from(foreground)),
+        Tile::Special {
+            special,
+            background,
+        } => {
+            let center = (TILE_SIZE / 2) as isize;
+            let (x, y) = (dx as isize - center, dy as isize - center);
+            let distance = x * x + y * y;
+            match special_sprite(special, distance) {
+                Some(color) => color,
+                None => element_color(Element::from(background)),
+            }
+        }
+    }
+}
+
+fn element_color(element: Element) -> Rgb {
+    match element {
+        Element::Grass => [0x2f, 0xa1, 0x2f],
+        Element::Dirt => [0x9c, 0x6b, 0x30],
+        Element::Mud => [0x5e, 0x45, 0x24],
+        Element::Ice => [0xc8, 0xe8, 0xf8],
+        Element::Downhill { direction } => {
+            let shade = 0x60 + direction * 0x10;
+            [0x20, shade, 0x20]
+        }
+        Element::Water => [0x20, 0x50, 0xd0],
+        Element::Acid => [0xa0, 0xe0, 0x20],
+        Element::WaterSwamp => [0x30, 0x70, 0x80],
+        Element::AcidSwamp => [0x70, 0x90, 0x30],
+        Element::Block => [0x70, 0x70, 0x70],
+        Element::StickyBlock => [0xc0, 0x90, 0x40],
+        Element::BouncyBlock => [0xd0, 0x40, 0x40],
+        Element::Other(_) => [0xff, 0x00, 0xff],
+    }
+}
+
+fn player_color(color: Color) -> Rgb {
+    match color {
+        Color::Blue => [0x30, 0x30, 0xff],
+        Color::Red => [0xff, 0x30, 0x30],
+        Color::Yellow => [0xff, 0xe0, 0x20],
+        Color::Green => [0x30, 0xff, 0x30],
+    }
+}
+
+/// Color of a special object at squared `distance` from the tile center,
+/// `None` where the background shows through.
+fn special_sprite(special: Special, distance: isize) -> Option<Rgb> {
+    let disc = |radius: isize, color: Rgb| (distance <= radius * radius).then_some(color);
+    let ring = |radius: isize, color: Rgb| {
+        (distance <= radius * radius && distance >= (radius - 2) * (radius - 2)).then_some(color)
+    };
+    match special {
+        Special::Start => disc(3, [0xff, 0xff, 0xff]),
+        Special::Hole => disc(5, [0x00, 0x00, 0x00]),
+        Special::FakeHole => ring(5, [0x00, 0x00, 0x00]),
+        Special::MovableBlock | Special::BreakableBlock { .. } => Some([0xa0, 0x80, 0x60]),
+        Special::Mine { big, exploded } => {
+            let radius = if big { 6 } else { 4 };
+            let color = if exploded {
+                [0x40, 0x30, 0x20]
+            } else {
+                [0x20, 0x20, 0x20]
+            };
+            disc(radius, color)
+        }
+        Special::TeleportStart(color) => disc(6, player_color(color)),
+        Special::TeleportExit(color) => ring(6, player_color(color)),
+        Special::MagnetAttract => disc(6, [0xc0, 0xc0, 0xc0]),
+        Special::MagnetRepel => ring(6, [0xc0, 0xc0, 0xc0]),
+        Special::PlayerStart(color) => disc(3, player_color(color)),
+        Special::Other(_) => disc(4, [0xff, 0x00, 0xff]),
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use protocol::track::Track;
+
+mod ascii;
+mod image;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Track files or directories of `.track` files
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Write a PNG per track into this directory
+    #[arg(short, long)]
+    png: Option<PathBuf>,
+
+    /// Print an ASCII preview, the default when --png is not given
+    #[arg(short, long)]
+    ascii: bool,
+}
+
+fn track_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut tracks: Vec<_> = fs::read_dir(path)
+                .with_context(|| format!("failed to read {}", path.display()))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "track"))
+                .collect();
+            tracks.sort();
+            files.extend(tracks);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn view(path: &Path, args: &Args) -> Result<()> {
+    let track: Track = fs::read_to_string(path)?.parse()?;
+    let map = track.decode_map()?;
+
+    if let Some(dir) = &args.png {
+        let file_name = path.with_extension("png");
+        let file_name = file_name.file_name().context("no file name")?;
+        let out = dir.join(file_name);
+        image::Image::render(&map).write_png(&out)?;
+        println!("{} -> {}", path.display(), out.display());
+    }
+
+    if args.ascii || args.png.is_none() {
+        println!("{} by {} ({})", track.name, track.author, path.display());
+        print!("{}", ascii::render(&map));
+        println!();
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if let Some(dir) = &args.png {
+        fs::create_dir_all(dir)?;
+    }
+
+    for path in track_files(&args.paths)? {
+        if let Err(e) = view(&path, &args) {
+            eprintln!("{}: {:?}", path.display(), e);
+        }
+    }
+    Ok(())
+}